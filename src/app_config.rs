@@ -9,6 +9,7 @@ pub enum AppConfigReadError {
 }
 
 #[derive(Debug)]
+#[allow(dead_code, clippy::enum_variant_names)]
 pub enum AppConfigGenError {
     ConfigFileReadError(toml::de::Error),
     ConfFileSerError(toml::ser::Error),
@@ -21,6 +22,8 @@ pub struct AppConfig {
     pub session_token_dir_path: String,
     pub templates_dir_path: String,
     pub hostname: String,
+    #[serde(default = "AppConfig::default_company_id")]
+    pub company_id: i64,
 }
 
 impl AppConfig {
@@ -34,6 +37,10 @@ impl AppConfig {
         panic!("Failed to get Config dir four your system!");
     }
 
+    fn default_company_id() -> i64 {
+        return 1;
+    }
+
     pub fn default() -> Self {
         let mut templates_path = Self::default_path();
         templates_path.pop();
@@ -55,6 +62,7 @@ impl AppConfig {
             templates_dir_path: templates_path.to_str().unwrap().to_string(),
             hostname: String::from("https://your.server.com"),
             session_token_dir_path: session_token_dir_path.to_str().unwrap().to_string(),
+            company_id: Self::default_company_id(),
         };
     }
 
@@ -87,6 +95,7 @@ impl AppConfig {
         return Ok(conf.unwrap());
     }
 
+    #[allow(clippy::unnecessary_fallible_conversions)]
     pub fn generate(path: &str, content: &str) -> Result<AppConfig, AppConfigGenError> {
         let instance = toml::from_str::<AppConfig>(content);
 
@@ -1,3 +1,4 @@
+use chrono::NaiveDate;
 use clap::{Parser, Subcommand};
 
 use crate::app_config::AppConfig;
@@ -20,7 +21,7 @@ pub struct Args {
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
-    /// Import an ODS file with a list of tasks as an invoice
+    /// Import a spreadsheet (ODS, XLSX, XLS, XLSB, CSV, TSV) with a list of tasks as an invoice
     Import(ImportArgs),
     /// Login to InvoiceShelf
     Login(LoginArgs),
@@ -35,6 +36,51 @@ pub struct ImportArgs {
     /// Path to the spreadsheet file
     #[arg(short, long)]
     pub spreadsheet: String,
+
+    /// Worksheet to read, by name or 0-based index. Overrides the template's `sheet`
+    #[arg(long)]
+    pub sheet: Option<String>,
+
+    /// Only import rows dated on or after this day (YYYY-MM-DD), on the template's `[filter]` date
+    #[arg(long)]
+    pub from: Option<NaiveDate>,
+
+    /// Only import rows dated on or before this day (YYYY-MM-DD), on the template's `[filter]` date
+    #[arg(long)]
+    pub to: Option<NaiveDate>,
+
+    /// Variable for the template's `[invoice]` fields, as `name=value`. Can be repeated
+    #[arg(long = "var", value_parser = parse_variable)]
+    pub vars: Vec<(String, String)>,
+
+    /// Invoice date (YYYY-MM-DD). Overrides the template's `[invoice]` date
+    #[arg(long)]
+    pub date: Option<NaiveDate>,
+
+    /// Days between the invoice date and the due date. Overrides the template's `[invoice]` terms
+    #[arg(long, conflicts_with = "due_date")]
+    pub due_in: Option<u32>,
+
+    /// Due date (YYYY-MM-DD). Overrides the template's `[invoice]` terms
+    #[arg(long)]
+    pub due_date: Option<NaiveDate>,
+
+    /// Customer to bill, by id, display name or email. Overrides the template's `[invoice]` customer
+    #[arg(long)]
+    pub customer: Option<String>,
+
+    /// Create customers that do not exist yet from the template's `[customer]` section, without asking
+    #[arg(long)]
+    pub create_missing_customers: bool,
+}
+
+fn parse_variable(text: &str) -> Result<(String, String), String> {
+    match text.split_once('=') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            return Ok((String::from(name.trim()), String::from(value)))
+        }
+        _ => return Err(format!("Expected name=value, got {}", text)),
+    }
 }
 
 #[derive(Parser, Debug)]
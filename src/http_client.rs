@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+
+use reqwest::{
+    blocking::{Client, RequestBuilder},
+    StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    app_config::AppConfig,
+    invoice_shelf::{
+        customer::{Customer, NewCustomer},
+        invoice::{Invoice, InvoiceSummary},
+        item::Item,
+        reference_data::{Country, Currency, TaxType, Unit},
+    },
+};
+
+// Payloads are only read through Debug, when the error is reported.
+#[derive(Debug)]
+#[allow(dead_code, clippy::enum_variant_names)]
+pub enum ApiRequestError {
+    NetworkingError(reqwest::Error),
+    /// The request could not be built, such as a body holding an amount too large to send.
+    RequestBuildingError(reqwest::Error),
+    ResponseParsingError(reqwest::Error),
+    AuthError(String),
+    ValidationError(ValidationErrors),
+    ServerError(StatusCode, String),
+    HttpError(StatusCode, String),
+}
+
+/// Body of a 422 response, listing the rejected fields and their messages.
+#[derive(Debug, Deserialize)]
+pub struct ValidationErrors {
+    pub message: String,
+    #[serde(default)]
+    pub errors: HashMap<String, Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct DataResponse<T> {
+    data: T,
+}
+
+#[derive(Serialize)]
+struct LoginBody<'a> {
+    username: &'a str,
+    password: &'a str,
+    device_name: &'a str,
+}
+
+#[derive(Deserialize)]
+struct LoginResponse {
+    token: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NextNumberResponse {
+    next_number: String,
+}
+
+/// Single entry point for every call made to the InvoiceShelf API.
+pub struct InvoiceShelfClient {
+    http_client: Client,
+    base_url: String,
+    company_id: i64,
+    token: Option<String>,
+}
+
+impl InvoiceShelfClient {
+    pub fn new(config: &AppConfig) -> Self {
+        return Self {
+            http_client: Client::new(),
+            base_url: Self::normalize_base_url(&config.hostname),
+            company_id: config.company_id,
+            token: None,
+        };
+    }
+
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(String::from(token));
+        return self;
+    }
+
+    /// Turns whatever was typed in the config into `scheme://host[/path]` without trailing slashes.
+    pub fn normalize_base_url(hostname: &str) -> String {
+        let trimmed = hostname.trim().trim_end_matches('/');
+
+        if trimmed.starts_with("http://") || trimmed.starts_with("https://") {
+            return String::from(trimmed);
+        }
+
+        return String::from("https://") + trimmed;
+    }
+
+    fn url(&self, path: &str) -> String {
+        return self.base_url.clone() + "/api/v1/" + path.trim_start_matches('/');
+    }
+
+    fn with_headers(&self, request: RequestBuilder) -> RequestBuilder {
+        let request = request
+            .header("Accept", "application/json")
+            .header("company", self.company_id.to_string());
+
+        if let Some(token) = &self.token {
+            return request.bearer_auth(token);
+        }
+
+        return request;
+    }
+
+    fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T, ApiRequestError> {
+        let request = self.http_client.get(self.url(path)).query(query);
+
+        return Self::read_response(self.with_headers(request).send());
+    }
+
+    fn post<B: Serialize + ?Sized, T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<T, ApiRequestError> {
+        let request = self.http_client.post(self.url(path)).json(body);
+
+        return Self::read_response(self.with_headers(request).send());
+    }
+
+    fn read_response<T: DeserializeOwned>(
+        resp: Result<reqwest::blocking::Response, reqwest::Error>,
+    ) -> Result<T, ApiRequestError> {
+        if let Err(e) = resp {
+            if e.is_builder() {
+                return Err(ApiRequestError::RequestBuildingError(e));
+            }
+
+            return Err(ApiRequestError::NetworkingError(e));
+        }
+
+        let u_resp = resp.unwrap();
+        let status = u_resp.status();
+
+        if status.is_success() {
+            let parsed = u_resp.json::<T>();
+
+            if let Err(e) = parsed {
+                return Err(ApiRequestError::ResponseParsingError(e));
+            }
+
+            return Ok(parsed.unwrap());
+        }
+
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                return Err(ApiRequestError::AuthError(
+                    u_resp.text().unwrap_or_default(),
+                ))
+            }
+            StatusCode::UNPROCESSABLE_ENTITY => {
+                let parsed = u_resp.json::<ValidationErrors>();
+
+                if let Err(e) = parsed {
+                    return Err(ApiRequestError::ResponseParsingError(e));
+                }
+
+                return Err(ApiRequestError::ValidationError(parsed.unwrap()));
+            }
+            s if s.is_server_error() => {
+                return Err(ApiRequestError::ServerError(
+                    s,
+                    u_resp.text().unwrap_or_default(),
+                ))
+            }
+            s => {
+                return Err(ApiRequestError::HttpError(
+                    s,
+                    u_resp.text().unwrap_or_default(),
+                ))
+            }
+        }
+    }
+
+    /// Logs in and returns the token to use for the next calls.
+    pub fn login(&self, username: &str, password: &str) -> Result<String, ApiRequestError> {
+        let resp = self.post::<_, LoginResponse>(
+            "auth/login",
+            &LoginBody {
+                username,
+                password,
+                device_name: "InvoiceShelfCli",
+            },
+        );
+
+        return resp.map(|r| r.token);
+    }
+
+    /// Whether the current token is still accepted by the server. Only the status is read,
+    /// as servers answer with `true`, `1` or an object depending on their version.
+    pub fn check_auth(&self) -> Result<bool, ApiRequestError> {
+        let request = self.http_client.get(self.url("auth/check"));
+        let resp = self.with_headers(request).send();
+
+        if let Err(e) = resp {
+            return Err(ApiRequestError::NetworkingError(e));
+        }
+
+        let resp = resp.unwrap();
+
+        if let Some(accepted) = Self::token_accepted(resp.status()) {
+            return Ok(accepted);
+        }
+
+        return Self::read_response::<bool>(Ok(resp));
+    }
+
+    /// Answer of `auth/check` told by its status, `None` when the status is an error of
+    /// its own.
+    fn token_accepted(status: StatusCode) -> Option<bool> {
+        match status {
+            s if s.is_success() => return Some(true),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => return Some(false),
+            _ => return None,
+        }
+    }
+
+    pub fn customers(&self, search: Option<&str>) -> Result<Vec<Customer>, ApiRequestError> {
+        let mut query = vec![("limit", "all")];
+
+        if let Some(s) = search {
+            query.push(("search", s));
+        }
+
+        return self
+            .get::<DataResponse<Vec<Customer>>>("customers", &query)
+            .map(|r| r.data);
+    }
+
+    pub fn customer(&self, id: i64) -> Result<Customer, ApiRequestError> {
+        return self
+            .get::<DataResponse<Customer>>(&format!("customers/{}", id), &[])
+            .map(|r| r.data);
+    }
+
+    pub fn create_customer(&self, customer: &NewCustomer) -> Result<Customer, ApiRequestError> {
+        return self
+            .post::<_, DataResponse<Customer>>("customers", customer)
+            .map(|r| r.data);
+    }
+
+    #[allow(dead_code)]
+    pub fn items(&self, search: Option<&str>) -> Result<Vec<Item>, ApiRequestError> {
+        let mut query = vec![("limit", "all")];
+
+        if let Some(s) = search {
+            query.push(("search", s));
+        }
+
+        return self
+            .get::<DataResponse<Vec<Item>>>("items", &query)
+            .map(|r| r.data);
+    }
+
+    pub fn invoices(
+        &self,
+        invoice_number: Option<&str>,
+    ) -> Result<Vec<InvoiceSummary>, ApiRequestError> {
+        let mut query = vec![("limit", "all")];
+
+        if let Some(n) = invoice_number {
+            query.push(("invoice_number", n));
+        }
+
+        return self
+            .get::<DataResponse<Vec<InvoiceSummary>>>("invoices", &query)
+            .map(|r| r.data);
+    }
+
+    pub fn create_invoice(&self, invoice: &Invoice) -> Result<InvoiceSummary, ApiRequestError> {
+        return self
+            .post::<_, DataResponse<InvoiceSummary>>("invoices", invoice)
+            .map(|r| r.data);
+    }
+
+    pub fn currencies(&self) -> Result<Vec<Currency>, ApiRequestError> {
+        return self
+            .get::<DataResponse<Vec<Currency>>>("currencies", &[])
+            .map(|r| r.data);
+    }
+
+    pub fn countries(&self) -> Result<Vec<Country>, ApiRequestError> {
+        return self
+            .get::<DataResponse<Vec<Country>>>("countries", &[])
+            .map(|r| r.data);
+    }
+
+    pub fn tax_types(&self) -> Result<Vec<TaxType>, ApiRequestError> {
+        return self
+            .get::<DataResponse<Vec<TaxType>>>("tax-types", &[("limit", "all")])
+            .map(|r| r.data);
+    }
+
+    #[allow(dead_code)]
+    pub fn units(&self) -> Result<Vec<Unit>, ApiRequestError> {
+        return self
+            .get::<DataResponse<Vec<Unit>>>("units", &[("limit", "all")])
+            .map(|r| r.data);
+    }
+
+    /// Company settings, such as `tax_per_item`, by name. Unset ones are `None`.
+    pub fn company_settings(
+        &self,
+        names: &[&str],
+    ) -> Result<HashMap<String, Option<String>>, ApiRequestError> {
+        let query: Vec<(&str, &str)> = names.iter().map(|n| ("settings[]", *n)).collect();
+        return self.get::<HashMap<String, Option<String>>>("company/settings", &query);
+    }
+
+    /// Next number of the company's sequence for `key` (`invoice`, `estimate`, `payment`).
+    pub fn next_number(&self, key: &str) -> Result<String, ApiRequestError> {
+        return self
+            .get::<NextNumberResponse>("next-number", &[("key", key)])
+            .map(|r| r.next_number);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_base_url() {
+        assert_eq!(
+            InvoiceShelfClient::normalize_base_url("https://my.server.com"),
+            "https://my.server.com"
+        );
+        assert_eq!(
+            InvoiceShelfClient::normalize_base_url("https://my.server.com///"),
+            "https://my.server.com"
+        );
+        assert_eq!(
+            InvoiceShelfClient::normalize_base_url(" my.server.com/ "),
+            "https://my.server.com"
+        );
+        assert_eq!(
+            InvoiceShelfClient::normalize_base_url("http://localhost:8080/invoices/"),
+            "http://localhost:8080/invoices"
+        );
+    }
+
+    #[test]
+    fn token_accepted() {
+        assert_eq!(
+            InvoiceShelfClient::token_accepted(StatusCode::OK),
+            Some(true)
+        );
+        assert_eq!(
+            InvoiceShelfClient::token_accepted(StatusCode::NO_CONTENT),
+            Some(true)
+        );
+        assert_eq!(
+            InvoiceShelfClient::token_accepted(StatusCode::UNAUTHORIZED),
+            Some(false)
+        );
+        assert_eq!(
+            InvoiceShelfClient::token_accepted(StatusCode::FORBIDDEN),
+            Some(false)
+        );
+        assert_eq!(
+            InvoiceShelfClient::token_accepted(StatusCode::INTERNAL_SERVER_ERROR),
+            None
+        );
+        assert_eq!(
+            InvoiceShelfClient::token_accepted(StatusCode::NOT_FOUND),
+            None
+        );
+    }
+
+    #[test]
+    fn url() {
+        let config = AppConfig {
+            session_token_dir_path: String::new(),
+            templates_dir_path: String::new(),
+            hostname: String::from("my.server.com/"),
+            company_id: 1,
+        };
+        let client = InvoiceShelfClient::new(&config);
+
+        assert_eq!(
+            client.url("auth/login"),
+            "https://my.server.com/api/v1/auth/login"
+        );
+        assert_eq!(
+            client.url("/invoices"),
+            "https://my.server.com/api/v1/invoices"
+        );
+    }
+}
@@ -0,0 +1,4 @@
+pub mod customer;
+pub mod invoice;
+pub mod item;
+pub mod reference_data;
@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Customer {
+    pub id: i64,
+    pub name: String,
+    pub email: Option<String>,
+    pub contact_name: Option<String>,
+    pub company_name: Option<String>,
+    #[allow(dead_code)]
+    pub currency_id: Option<i64>,
+}
+
+/// Body of the customer creation request.
+#[derive(Debug, Clone, Serialize)]
+pub struct NewCustomer {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency_id: Option<i64>,
+    /// VAT number.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tax_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub billing: Option<Address>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Address {
+    pub name: Option<String>,
+    pub address_street_1: Option<String>,
+    pub address_street_2: Option<String>,
+    pub city: Option<String>,
+    pub state: Option<String>,
+    pub zip: Option<String>,
+    pub country_id: Option<i64>,
+}
+
+impl Customer {
+    fn searchable(&self) -> Vec<&str> {
+        let mut fields = vec![self.name.as_str()];
+
+        for field in [&self.company_name, &self.contact_name, &self.email]
+            .into_iter()
+            .flatten()
+        {
+            fields.push(field);
+        }
+
+        return fields;
+    }
+
+    /// Whether the display name, company, contact or email is `query`, ignoring case.
+    pub fn is_exact_match(&self, query: &str) -> bool {
+        let query = query.trim().to_lowercase();
+        return self.searchable().iter().any(|f| f.to_lowercase() == query);
+    }
+
+    /// Whether the display name, company, contact or email contains `query`, ignoring case.
+    pub fn is_partial_match(&self, query: &str) -> bool {
+        let query = query.trim().to_lowercase();
+        return self
+            .searchable()
+            .iter()
+            .any(|f| f.to_lowercase().contains(&query));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching() {
+        let customer = Customer {
+            id: 3,
+            name: String::from("Acme"),
+            email: Some(String::from("billing@acme.test")),
+            contact_name: Some(String::from("Wile E. Coyote")),
+            company_name: Some(String::from("Acme Corporation")),
+            currency_id: None,
+        };
+
+        assert!(customer.is_exact_match("acme"));
+        assert!(customer.is_exact_match(" BILLING@acme.test "));
+        assert!(customer.is_exact_match("acme corporation"));
+        assert!(!customer.is_exact_match("acm"));
+
+        assert!(customer.is_partial_match("corp"));
+        assert!(customer.is_partial_match("coyote"));
+        assert!(!customer.is_partial_match("globex"));
+    }
+}
@@ -1,98 +1,360 @@
-use crate::template_mapping::ComputedMappingOutput;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 
+use crate::{
+    invoice_shelf::reference_data::TaxType,
+    money::{self, serialize_cents, serialize_decimal},
+    template_mapping::{
+        invoice_fields::{ComputedInvoiceFields, Discount},
+        ComputedMappingOutput,
+    },
+};
+
+#[derive(Debug, Deserialize)]
+pub struct InvoiceSummary {
+    pub id: i64,
+    pub invoice_number: String,
+}
+
+#[derive(Debug, Serialize)]
 pub struct Invoice {
+    #[serde(rename = "invoice_date")]
     date: String,
     due_date: String,
-    customer_id: i32,
+    customer_id: i64,
     invoice_number: String,
-    exchange_rate: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency_id: Option<i64>,
+    exchange_rate: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    notes: Option<String>,
     discount_type: String,
-    discount: f32,
-    discount_val: f32,
-    sub_total: f32,
-    total: f32,
-    tax: f32,
+    /// Amount or percentage, as typed in the InvoiceShelf form. `discount_val` holds
+    /// the amount taken off, in cents.
+    #[serde(serialize_with = "serialize_decimal")]
+    discount: Decimal,
+    #[serde(serialize_with = "serialize_cents")]
+    discount_val: Decimal,
+    #[serde(serialize_with = "serialize_cents")]
+    sub_total: Decimal,
+    #[serde(serialize_with = "serialize_cents")]
+    total: Decimal,
+    #[serde(serialize_with = "serialize_cents")]
+    tax: Decimal,
+    taxes: Vec<InvoiceTax>,
     template_name: String,
     items: Vec<InvoiceItem>,
 }
 
+#[derive(Debug, Serialize)]
 pub struct InvoiceItem {
     name: String,
-    quantity: i64,
-    price: f64,
+    #[serde(serialize_with = "serialize_decimal")]
+    quantity: Decimal,
+    #[serde(serialize_with = "serialize_cents")]
+    price: Decimal,
     description: String,
-    item_id: i64,
-    sub_total: f64,
-    total: f64,
+    item_id: Option<i64>,
+    #[serde(serialize_with = "serialize_cents")]
+    sub_total: Decimal,
+    #[serde(serialize_with = "serialize_cents")]
+    total: Decimal,
     unit_name: String,
-    discount: f64,
+    #[serde(serialize_with = "serialize_cents")]
+    discount: Decimal,
     discount_type: String,
-    discount_val: f64,
+    #[serde(serialize_with = "serialize_cents")]
+    discount_val: Decimal,
+    #[serde(serialize_with = "serialize_cents")]
+    tax: Decimal,
+    taxes: Vec<InvoiceTax>,
+}
+
+/// Tax type applied to an item or to the whole invoice, with the amount it adds.
+#[derive(Debug, Serialize)]
+pub struct InvoiceTax {
+    tax_type_id: i64,
+    name: String,
+    #[serde(serialize_with = "serialize_decimal")]
+    percent: Decimal,
+    compound_tax: i64,
+    #[serde(serialize_with = "serialize_cents")]
+    amount: Decimal,
+}
+
+impl InvoiceTax {
+    /// Taxes on `base`, each rounded half-up to the cent as InvoiceShelf does. Compound
+    /// taxes apply to the base plus the other taxes.
+    fn compute(tax_types: &[TaxType], base: Decimal) -> Vec<InvoiceTax> {
+        let amount =
+            |base: Decimal, t: &TaxType| money::round(base * t.percent / Decimal::ONE_HUNDRED, 2);
+
+        let simple: Decimal = tax_types
+            .iter()
+            .filter(|t| t.compound_tax == 0)
+            .map(|t| amount(base, t))
+            .sum();
+
+        return tax_types
+            .iter()
+            .map(|t| InvoiceTax {
+                tax_type_id: t.id,
+                name: t.name.clone(),
+                percent: t.percent,
+                compound_tax: t.compound_tax,
+                amount: if t.compound_tax == 0 {
+                    amount(base, t)
+                } else {
+                    amount(base + simple, t)
+                },
+            })
+            .collect();
+    }
 }
 
 impl Invoice {
-    pub fn from_generated_items(items: Vec<ComputedMappingOutput>) -> Self {
-        let invoice_items = items
+    pub fn from_generated_items(
+        items: Vec<ComputedMappingOutput>,
+        fields: &ComputedInvoiceFields,
+        customer_id: i64,
+    ) -> Self {
+        let invoice_items: Vec<InvoiceItem> = items
             .iter()
             .map(|i| InvoiceItem {
                 name: i.name.clone(),
                 quantity: i.quantity,
                 price: i.price,
                 description: i.description.clone(),
-                item_id: 1,
+                item_id: None,
                 sub_total: i.sub_total,
                 total: i.total,
                 unit_name: i.unit_name.clone(),
-                discount: 0.0,
-                discount_type: "".into(),
-                discount_val: 0.0,
+                discount: Decimal::ZERO,
+                discount_type: "fixed".into(),
+                discount_val: Decimal::ZERO,
+                tax: Decimal::ZERO,
+                taxes: Vec::new(),
             })
             .collect();
 
+        let sub_total: Decimal = invoice_items.iter().map(|i| i.sub_total).sum();
+        let total: Decimal = invoice_items.iter().map(|i| i.total).sum();
+
+        let (discount_type, discount, discount_val) = match fields.discount {
+            Some(Discount::Fixed(amount)) => ("fixed", amount, amount),
+            Some(Discount::Percentage(percent)) => (
+                "percentage",
+                percent,
+                money::round(sub_total * percent / Decimal::ONE_HUNDRED, 2),
+            ),
+            None => ("fixed", Decimal::ZERO, Decimal::ZERO),
+        };
+
         return Self {
-            date: chrono::offset::Local::now().to_rfc3339(),
-            due_date: chrono::offset::Local::now().to_rfc3339(),
-            customer_id: 1,
-            invoice_number: "a".into(),
+            date: fields.date.format("%Y-%m-%d").to_string(),
+            due_date: fields.due_date.format("%Y-%m-%d").to_string(),
+            customer_id,
+            invoice_number: fields.number.clone().unwrap_or_default(),
+            currency_id: None,
             exchange_rate: 1.0,
-            discount_type: "".into(),
-            discount: 0.0,
-            discount_val: 0.0,
-            sub_total: 0.0,
-            total: 0.0,
-            tax: 0.0,
-            template_name: "".into(),
+            notes: fields.notes.clone(),
+            discount_type: discount_type.into(),
+            discount,
+            discount_val,
+            sub_total,
+            total: total - discount_val,
+            tax: Decimal::ZERO,
+            taxes: Vec::new(),
+            template_name: fields.template_name.clone(),
             items: invoice_items,
         };
     }
+
+    pub fn with_currency(mut self, currency_id: i64) -> Self {
+        self.currency_id = Some(currency_id);
+        return self;
+    }
+
+    /// Applies tax types to the whole invoice, after its discount, and to each item, then
+    /// adds them to the total.
+    pub fn with_taxes(mut self, invoice_taxes: &[TaxType], item_taxes: &[Vec<TaxType>]) -> Self {
+        for (item, tax_types) in self.items.iter_mut().zip(item_taxes) {
+            item.taxes = InvoiceTax::compute(tax_types, item.total);
+            item.tax = item.taxes.iter().map(|t| t.amount).sum();
+        }
+
+        self.taxes = InvoiceTax::compute(invoice_taxes, self.total);
+        self.tax = self.taxes.iter().map(|t| t.amount).sum::<Decimal>()
+            + self.items.iter().map(|i| i.tax).sum::<Decimal>();
+        self.total += self.tax;
+
+        return self;
+    }
+
+    pub fn sub_total(&self) -> Decimal {
+        return self.sub_total;
+    }
+
+    pub fn tax(&self) -> Decimal {
+        return self.tax;
+    }
+
+    pub fn total(&self) -> Decimal {
+        return self.total;
+    }
 }
 
-impl InvoiceItem {
-    fn new(
-        name: String,
-        quantity: i64,
-        price: f64,
-        description: String,
-        item_id: i64,
-        sub_total: f64,
-        total: f64,
-        unit_name: String,
-        discount: f64,
-        discount_type: String,
-        discount_val: f64,
-    ) -> Self {
-        return Self {
-            name,
-            quantity,
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    fn item(price: Decimal) -> ComputedMappingOutput {
+        return ComputedMappingOutput {
+            name: String::from("Task"),
+            quantity: dec!(1.5),
             price,
-            description,
-            item_id,
-            sub_total,
-            total,
-            unit_name,
-            discount,
-            discount_type,
-            discount_val,
+            description: String::new(),
+            sub_total: price,
+            total: price,
+            unit_name: String::from("h"),
+            taxes: Vec::new(),
+        };
+    }
+
+    fn fields() -> ComputedInvoiceFields {
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 10, 31).unwrap();
+
+        return ComputedInvoiceFields {
+            customer: None,
+            number: None,
+            date,
+            due_date: date + chrono::Duration::days(30),
+            currency: None,
+            notes: None,
+            discount: None,
+            taxes: Vec::new(),
+            template_name: String::from("invoice1"),
         };
     }
+
+    #[test]
+    fn payload_amounts_in_cents() {
+        let invoice =
+            Invoice::from_generated_items(vec![item(dec!(0.1)), item(dec!(0.2))], &fields(), 1);
+
+        let payload = serde_json::to_value(&invoice).unwrap();
+
+        assert_eq!(payload["customer_id"], 1);
+        assert_eq!(payload["sub_total"], 30);
+        assert_eq!(payload["total"], 30);
+        assert_eq!(payload["items"][0]["price"], 10);
+        assert_eq!(payload["items"][1]["total"], 20);
+        assert_eq!(payload["items"][0]["quantity"], "1.5");
+    }
+
+    #[test]
+    fn payload_invoice_fields() {
+        let mut fields = fields();
+        fields.number = Some(String::from("INV-7"));
+        fields.notes = Some(String::from("Thanks"));
+        fields.discount = Some(Discount::Percentage(dec!(10)));
+
+        let invoice =
+            Invoice::from_generated_items(vec![item(dec!(12.34))], &fields, 3).with_currency(2);
+
+        let payload = serde_json::to_value(&invoice).unwrap();
+
+        assert_eq!(payload["invoice_date"], "2024-10-31");
+        assert_eq!(payload["due_date"], "2024-11-30");
+        assert_eq!(payload["customer_id"], 3);
+        assert_eq!(payload["invoice_number"], "INV-7");
+        assert_eq!(payload["currency_id"], 2);
+        assert_eq!(payload["notes"], "Thanks");
+        assert_eq!(payload["template_name"], "invoice1");
+        assert_eq!(payload["discount_type"], "percentage");
+        assert_eq!(payload["discount"], "10");
+        assert_eq!(payload["discount_val"], 123);
+        assert_eq!(payload["sub_total"], 1234);
+        assert_eq!(payload["total"], 1111);
+
+        fields.discount = Some(Discount::Fixed(dec!(2.5)));
+        let payload = serde_json::to_value(Invoice::from_generated_items(
+            vec![item(dec!(12.34))],
+            &fields,
+            3,
+        ))
+        .unwrap();
+
+        assert_eq!(payload["discount_type"], "fixed");
+        assert_eq!(payload["discount_val"], 250);
+        assert_eq!(payload["total"], 984);
+        assert!(payload.get("currency_id").is_none());
+    }
+
+    fn tax_type(id: i64, name: &str, percent: Decimal, compound_tax: i64) -> TaxType {
+        return TaxType {
+            id,
+            name: String::from(name),
+            percent,
+            compound_tax,
+            description: None,
+        };
+    }
+
+    #[test]
+    fn payload_invoice_taxes() {
+        let mut fields = fields();
+        fields.discount = Some(Discount::Percentage(dec!(10)));
+        let vat = tax_type(1, "TVA 20%", dec!(20), 0);
+
+        let invoice =
+            Invoice::from_generated_items(vec![item(dec!(12.34)), item(dec!(0.05))], &fields, 3)
+                .with_taxes(&[vat], &[]);
+
+        assert_eq!(invoice.sub_total(), dec!(12.39));
+        assert_eq!(invoice.tax(), dec!(2.23));
+        assert_eq!(invoice.total(), dec!(13.38));
+
+        let payload = serde_json::to_value(&invoice).unwrap();
+
+        assert_eq!(payload["discount_val"], 124);
+        assert_eq!(payload["tax"], 223);
+        assert_eq!(payload["total"], 1338);
+        assert_eq!(payload["taxes"][0]["tax_type_id"], 1);
+        assert_eq!(payload["taxes"][0]["name"], "TVA 20%");
+        assert_eq!(payload["taxes"][0]["percent"], "20");
+        assert_eq!(payload["taxes"][0]["amount"], 223);
+        assert_eq!(payload["items"][0]["tax"], 0);
+    }
+
+    #[test]
+    fn payload_item_taxes() {
+        let vat = tax_type(1, "TVA 20%", dec!(20), 0);
+        let reduced = tax_type(2, "TVA 5,5%", dec!(5.5), 0);
+        let compound = tax_type(3, "Local", dec!(10), 1);
+        let levy = tax_type(4, "Levy", dec!(20), 0);
+
+        let invoice = Invoice::from_generated_items(
+            vec![item(dec!(0.05)), item(dec!(10)), item(dec!(7))],
+            &fields(),
+            3,
+        )
+        .with_taxes(&[], &[vec![vat, levy], vec![reduced, compound], vec![]]);
+
+        let payload = serde_json::to_value(&invoice).unwrap();
+
+        // 20% of 5 cents is 1 cent, rounded on every tax rather than on their sum.
+        assert_eq!(payload["items"][0]["tax"], 2);
+        assert_eq!(payload["items"][0]["taxes"][1]["amount"], 1);
+        assert_eq!(payload["items"][1]["taxes"][0]["amount"], 55);
+        assert_eq!(payload["items"][1]["taxes"][1]["amount"], 106);
+        assert_eq!(payload["items"][1]["tax"], 161);
+        assert_eq!(payload["items"][2]["tax"], 0);
+        assert_eq!(payload["sub_total"], 1705);
+        assert_eq!(payload["tax"], 163);
+        assert_eq!(payload["total"], 1868);
+        assert_eq!(payload["taxes"], serde_json::json!([]));
+    }
 }
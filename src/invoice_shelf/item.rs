@@ -0,0 +1,13 @@
+#![allow(dead_code)]
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Item {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    /// Price in cents.
+    pub price: i64,
+    pub unit_id: Option<i64>,
+}
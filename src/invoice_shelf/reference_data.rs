@@ -0,0 +1,79 @@
+// The models mirror the InvoiceShelf payloads, whether or not the import reads every field.
+#![allow(dead_code)]
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Currency {
+    pub id: i64,
+    pub name: String,
+    pub code: String,
+    pub symbol: Option<String>,
+    pub precision: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TaxType {
+    pub id: i64,
+    pub name: String,
+    pub percent: Decimal,
+    /// Non-zero when the tax applies on top of the other taxes.
+    #[serde(default)]
+    pub compound_tax: i64,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Unit {
+    pub id: i64,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Country {
+    pub id: i64,
+    pub code: String,
+    pub name: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    #[test]
+    fn tax_type_percent() {
+        let tax_type: TaxType =
+            serde_json::from_str(r#"{"id": 2, "name": "TVA 5,5%", "percent": 5.5}"#).unwrap();
+        assert_eq!(tax_type.percent, dec!(5.5));
+
+        let tax_type: TaxType =
+            serde_json::from_str(r#"{"id": 2, "name": "TVA 5,5%", "percent": "5.50"}"#).unwrap();
+        assert_eq!(tax_type.percent, dec!(5.5));
+
+        assert!(
+            serde_json::from_str::<TaxType>(r#"{"id": 2, "name": "TVA", "percent": "n/a"}"#)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn reference_payloads() {
+        let currency: Currency = serde_json::from_str(
+            r#"{"id": 12, "name": "Euro", "code": "EUR", "symbol": "€", "precision": 2}"#,
+        )
+        .unwrap();
+        assert_eq!(currency.code, "EUR");
+        assert_eq!(currency.symbol.as_deref(), Some("€"));
+        assert_eq!(currency.precision, 2);
+
+        let unit: Unit = serde_json::from_str(r#"{"id": 1, "name": "hour"}"#).unwrap();
+        assert_eq!(unit.name, "hour");
+
+        let country: Country =
+            serde_json::from_str(r#"{"id": 75, "code": "FR", "name": "France"}"#).unwrap();
+        assert_eq!(country.name, "France");
+    }
+}
@@ -1,3 +1,12 @@
+// The code returns explicitly and checks results with `if let Err(e)` or `is_err()`
+// before unwrapping them, rather than with `?`.
+#![allow(
+    clippy::needless_return,
+    clippy::question_mark,
+    clippy::redundant_pattern_matching,
+    clippy::unnecessary_unwrap
+)]
+
 use std::process::ExitCode;
 
 use clap::Parser;
@@ -8,6 +17,9 @@ use navigation::{
 
 mod app_config;
 mod cli;
+mod http_client;
+mod invoice_shelf;
+mod money;
 mod navigation;
 mod session;
 mod spreadsheet_parsing;
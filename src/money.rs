@@ -0,0 +1,92 @@
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{ser::Error, Serializer};
+
+/// Decimals kept on amounts when the currency does not say otherwise.
+pub const DEFAULT_PRECISION: u32 = 2;
+
+/// Rounds half-up (away from zero on a tie), the way invoices are expected to be rounded.
+pub fn round(value: Decimal, precision: u32) -> Decimal {
+    return value.round_dp_with_strategy(precision, RoundingStrategy::MidpointAwayFromZero);
+}
+
+/// InvoiceShelf stores every amount as an integer number of cents. Fails on amounts too
+/// large for that, rather than sending a wrong one.
+pub fn to_cents(value: Decimal) -> Result<i64, String> {
+    let too_large = || format!("{} is too large to be sent as cents", value);
+    let cents = round(value, 2).checked_mul(Decimal::ONE_HUNDRED);
+
+    if let None = cents {
+        return Err(too_large());
+    }
+
+    // A 2-decimal value times 100 is a whole number, only its size can be a problem.
+    return i64::try_from(cents.unwrap().trunc()).map_err(|_| too_large());
+}
+
+pub fn serialize_cents<S: Serializer>(value: &Decimal, serializer: S) -> Result<S::Ok, S::Error> {
+    let cents = to_cents(*value);
+
+    if let Err(e) = cents {
+        return Err(S::Error::custom(e));
+    }
+
+    return serializer.serialize_i64(cents.unwrap());
+}
+
+/// Exact decimal text, for values such as quantities that are not sent as cents. Numbers
+/// would go through a float on the way to JSON.
+pub fn serialize_decimal<S: Serializer>(value: &Decimal, serializer: S) -> Result<S::Ok, S::Error> {
+    return serializer.serialize_str(&value.normalize().to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    #[test]
+    fn round_half_up() {
+        assert_eq!(round(dec!(2.675), 2), dec!(2.68));
+        assert_eq!(round(dec!(2.665), 2), dec!(2.67));
+        assert_eq!(round(dec!(-2.675), 2), dec!(-2.68));
+        assert_eq!(round(dec!(2.5), 0), dec!(3));
+        assert_eq!(round(dec!(10.1234), 3), dec!(10.123));
+    }
+
+    #[test]
+    fn exact_cents() {
+        assert_eq!(to_cents(dec!(0.1) + dec!(0.2)), Ok(30));
+        assert_eq!(to_cents(dec!(1.005)), Ok(101));
+        assert_eq!(to_cents(dec!(-43.5)), Ok(-4350));
+        assert_eq!(to_cents(dec!(123456789.99)), Ok(12345678999));
+    }
+
+    #[test]
+    fn cents_overflow() {
+        assert!(to_cents(dec!(92233720368547758.08)).is_err());
+        assert!(to_cents(dec!(-92233720368547758.09)).is_err());
+        assert_eq!(to_cents(dec!(92233720368547758.07)), Ok(i64::MAX));
+
+        #[derive(serde::Serialize)]
+        struct Amount(#[serde(serialize_with = "serialize_cents")] Decimal);
+
+        assert!(serde_json::to_value(Amount(Decimal::MAX)).is_err());
+    }
+
+    #[test]
+    fn exact_decimals() {
+        #[derive(serde::Serialize)]
+        struct Quantity(#[serde(serialize_with = "serialize_decimal")] Decimal);
+
+        assert_eq!(
+            serde_json::to_value(Quantity(dec!(0.1) + dec!(0.2))).unwrap(),
+            "0.3"
+        );
+        assert_eq!(serde_json::to_value(Quantity(dec!(1.50))).unwrap(), "1.5");
+        assert_eq!(
+            serde_json::to_value(Quantity(dec!(123456789012345678.123456789))).unwrap(),
+            "123456789012345678.123456789"
+        );
+    }
+}
@@ -1,3 +1,4 @@
 pub mod config_file_creation;
+pub mod customer;
 pub mod import;
 pub mod login;
@@ -0,0 +1,311 @@
+use inquire::{Confirm, Select, Text};
+use reqwest::StatusCode;
+
+use crate::{
+    http_client::{ApiRequestError, InvoiceShelfClient},
+    invoice_shelf::{
+        customer::{Address, Customer, NewCustomer},
+        reference_data::Country,
+    },
+    template_mapping::{customer_fields::ComputedCustomerFields, CustomerRef},
+};
+
+use super::import::{fetch_currencies, find_currency, print_validation_errors};
+
+/// Finds the customer an import refers to, by id, display name or email, asking the user
+/// to choose when several match. An id no customer has is looked up as a name, since names
+/// can be made of digits too. Returns `None` when nothing matches.
+pub fn resolve_customer_prompt(
+    client: &InvoiceShelfClient,
+    reference: &CustomerRef,
+) -> Option<Customer> {
+    let query = match reference {
+        CustomerRef::Id(id) => {
+            let customer = client.customer(*id);
+
+            match customer {
+                Ok(customer) => return Some(customer),
+                Err(ApiRequestError::HttpError(StatusCode::NOT_FOUND, _)) => id.to_string(),
+                Err(e) => panic!(
+                    "Something wrong happened while fetching customer {} :  {:?}",
+                    id, e
+                ),
+            }
+        }
+        CustomerRef::Name(query) => query.clone(),
+    };
+    let query = query.as_str();
+
+    let customers = client.customers(Some(query));
+
+    if let Err(e) = customers {
+        panic!(
+            "Something wrong happened while looking for customer {} :  {:?}",
+            query, e
+        );
+    }
+
+    let mut customers: Vec<Customer> = customers
+        .unwrap()
+        .into_iter()
+        .filter(|c| c.is_partial_match(query))
+        .collect();
+
+    // An exact name or email wins over customers merely containing it.
+    if customers.iter().any(|c| c.is_exact_match(query)) {
+        customers.retain(|c| c.is_exact_match(query));
+    }
+
+    if customers.len() <= 1 {
+        return customers.pop();
+    }
+
+    return Some(select_customer_prompt(
+        &format!("Several customers match \"{}\", which one?", query),
+        customers,
+    ));
+}
+
+/// Creates the customer `name` out of the `[customer]` fields of the template. Unless
+/// `batch` is set, asks first and lets the user review every field.
+pub fn create_customer_prompt(
+    client: &InvoiceShelfClient,
+    name: &str,
+    fields: ComputedCustomerFields,
+    batch: bool,
+) -> Customer {
+    if !batch {
+        let answer = Confirm::new(&format!(
+            "No customer matches \"{}\" by name or email. Create it?",
+            name
+        ))
+        .with_default(true)
+        .prompt();
+
+        if let Err(e) = answer {
+            panic!("Something wrong happened while interacting: {}", e);
+        }
+
+        if !answer.unwrap() {
+            panic!(
+                "No customer on InvoiceShelf matches \"{}\" by name or email.",
+                name
+            );
+        }
+    }
+
+    let mut name = String::from(name);
+    let mut fields = fields;
+
+    loop {
+        if !batch {
+            (name, fields) = review_customer_prompt(name, fields);
+        }
+
+        let customer = new_customer(client, &name, &fields);
+
+        if let Err(message) = customer {
+            if batch {
+                panic!("Could not create customer {}: {}", name, message);
+            }
+
+            println!("{}", message);
+            continue;
+        }
+
+        match client.create_customer(&customer.unwrap()) {
+            Ok(created) => {
+                println!(
+                    "Customer {} created successfully with id {} !",
+                    created.name, created.id
+                );
+                return created;
+            }
+            Err(ApiRequestError::ValidationError(e)) => {
+                println!("InvoiceShelf rejected the customer: {}", e.message);
+                print_validation_errors(&e);
+
+                if batch {
+                    panic!("Could not create customer {}.", name);
+                }
+            }
+            Err(e) => {
+                panic!(
+                    "Something wrong happened while creating customer {} :  {:?}",
+                    name, e
+                )
+            }
+        }
+    }
+}
+
+/// Lets the user edit the fields of a customer about to be created, until they accept them.
+fn review_customer_prompt(
+    name: String,
+    fields: ComputedCustomerFields,
+) -> (String, ComputedCustomerFields) {
+    let ask = |label: &str, value: Option<String>| {
+        let answer = Text::new(label)
+            .with_initial_value(value.as_deref().unwrap_or(""))
+            .prompt();
+
+        if let Err(e) = answer {
+            panic!("Something wrong happened while interacting: {}", e);
+        }
+
+        return Some(String::from(answer.unwrap().trim())).filter(|a| !a.is_empty());
+    };
+
+    let mut name = name;
+    let mut fields = fields;
+
+    loop {
+        println!("New customer, empty fields are left out:");
+
+        name = ask("Name:", Some(name.clone())).unwrap_or(name);
+        fields = ComputedCustomerFields {
+            email: ask("Email:", fields.email),
+            currency: ask("Currency code:", fields.currency),
+            vat_number: ask("VAT number:", fields.vat_number),
+            street: ask("Street:", fields.street),
+            street_2: ask("Street, second line:", fields.street_2),
+            city: ask("City:", fields.city),
+            state: ask("State:", fields.state),
+            zip: ask("Zip code:", fields.zip),
+            country: ask("Country code:", fields.country),
+        };
+
+        let answer = Confirm::new(&format!("Create customer {}?", name))
+            .with_default(true)
+            .prompt();
+
+        if let Err(e) = answer {
+            panic!("Something wrong happened while interacting: {}", e);
+        }
+
+        if answer.unwrap() {
+            return (name, fields);
+        }
+    }
+}
+
+/// Builds the creation request, looking up the currency and country codes.
+pub fn new_customer(
+    client: &InvoiceShelfClient,
+    name: &str,
+    fields: &ComputedCustomerFields,
+) -> Result<NewCustomer, String> {
+    let mut currency_id = None;
+
+    if let Some(code) = &fields.currency {
+        let currencies = fetch_currencies(client);
+        let currency = find_currency(&currencies, code);
+
+        if let None = currency {
+            return Err(format!("Unknown currency {}.", code));
+        }
+
+        currency_id = Some(currency.unwrap().id);
+    }
+
+    let mut country_id = None;
+
+    if let Some(code) = &fields.country {
+        let country = find_country(client, code);
+
+        if let None = country {
+            return Err(format!("Unknown country {}.", code));
+        }
+
+        country_id = Some(country.unwrap().id);
+    }
+
+    let address = Address {
+        name: Some(String::from(name)),
+        address_street_1: fields.street.clone(),
+        address_street_2: fields.street_2.clone(),
+        city: fields.city.clone(),
+        state: fields.state.clone(),
+        zip: fields.zip.clone(),
+        country_id,
+    };
+    let has_address = [
+        &fields.street,
+        &fields.street_2,
+        &fields.city,
+        &fields.state,
+        &fields.zip,
+        &fields.country,
+    ]
+    .iter()
+    .any(|f| f.is_some());
+
+    return Ok(NewCustomer {
+        name: String::from(name),
+        email: fields.email.clone(),
+        currency_id,
+        tax_id: fields.vat_number.clone(),
+        billing: if has_address { Some(address) } else { None },
+    });
+}
+
+fn find_country(client: &InvoiceShelfClient, code: &str) -> Option<Country> {
+    let countries = client.countries();
+
+    if let Err(e) = countries {
+        panic!(
+            "Something wrong happened while fetching countries :  {:?}",
+            e
+        );
+    }
+
+    return countries
+        .unwrap()
+        .into_iter()
+        .find(|c| c.code.eq_ignore_ascii_case(code));
+}
+
+/// Asks which customer to bill, among all of them, when the import names none.
+pub fn choose_customer_prompt(client: &InvoiceShelfClient) -> Customer {
+    let customers = client.customers(None);
+
+    if let Err(e) = customers {
+        panic!(
+            "Something wrong happened while fetching customers :  {:?}",
+            e
+        );
+    }
+
+    let mut customers = customers.unwrap();
+
+    if customers.is_empty() {
+        panic!("There is no customer on InvoiceShelf to bill.");
+    }
+
+    if customers.len() == 1 {
+        return customers.pop().unwrap();
+    }
+
+    return select_customer_prompt("Which customer should be billed?", customers);
+}
+
+fn select_customer_prompt(message: &str, mut customers: Vec<Customer>) -> Customer {
+    let labels: Vec<String> = customers
+        .iter()
+        .map(|c| match &c.email {
+            Some(email) => format!("{} <{}> (#{})", c.name, email, c.id),
+            None => format!("{} (#{})", c.name, c.id),
+        })
+        .collect();
+
+    let choice = Select::new(message, labels.clone()).prompt();
+
+    if let Err(e) = choice {
+        panic!("Something wrong happened while interacting: {}", e);
+    }
+
+    let choice = choice.unwrap();
+    let index = labels.iter().position(|l| *l == choice).unwrap();
+
+    return customers.swap_remove(index);
+}
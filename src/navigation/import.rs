@@ -1,8 +1,30 @@
-use std::path::PathBuf;
+use std::{collections::HashMap, path::PathBuf, str::FromStr};
+
+use inquire::Confirm;
+use rust_decimal::Decimal;
 
 use crate::{
-    app_config::AppConfig, cli::ImportArgs, navigation::login::login_prompt,
-    spreadsheet_parsing::spreadsheet_data::TaskList, template_mapping::TemplateMapping,
+    app_config::AppConfig,
+    cli::ImportArgs,
+    http_client::{ApiRequestError, InvoiceShelfClient, ValidationErrors},
+    invoice_shelf::{
+        customer::Customer,
+        invoice::Invoice,
+        reference_data::{Currency, TaxType},
+    },
+    navigation::{
+        customer::{
+            choose_customer_prompt, create_customer_prompt, new_customer, resolve_customer_prompt,
+        },
+        login::resume_session_prompt,
+    },
+    spreadsheet_parsing::spreadsheet_data::{SheetSelector, TaskList},
+    template_mapping::{
+        customer_fields::ComputedCustomerFields,
+        invoice_fields::{ComputedInvoiceFields, PaymentTerms},
+        numbering::NumberPattern,
+        ComputedMappingOutput, CustomerRef, FilterSummary, RowFilter, TemplateMapping,
+    },
 };
 
 pub fn import_prompt(app_config: &AppConfig, args: &ImportArgs) {
@@ -24,31 +46,534 @@ pub fn import_prompt(app_config: &AppConfig, args: &ImportArgs) {
         );
     }
 
-    let session_path = PathBuf::from(&app_config.session_token_dir_path);
+    let session = resume_session_prompt(app_config);
+
+    let mapping = TemplateMapping::from_file(template_path.to_str().unwrap());
+
+    if let Err(e) = mapping {
+        panic!("Failed to read your mapping configuration: {:?}", e);
+    }
+
+    let mut mapping = mapping.unwrap();
+    mapping.set_date_bounds(args.from, args.to);
 
-    if !session_path.is_file() {
-        println!("It seems you are not loogged in, please log in now. ",);
-        login_prompt(&None, &None, app_config);
+    let terms = match (args.due_in, args.due_date) {
+        (Some(days), _) => Some(PaymentTerms::Net(i64::from(days))),
+        (_, Some(date)) => Some(PaymentTerms::Date(date)),
+        _ => None,
+    };
+    mapping.set_invoice_dates(args.date, terms);
+
+    if let Some(customer) = &args.customer {
+        mapping.set_customer(CustomerRef::parse(customer));
     }
 
-    let lines = TaskList::try_from_path(excel_path.to_str().unwrap());
+    let mut reading_options = mapping.reading_options();
+
+    if let Some(sheet) = &args.sheet {
+        reading_options.sheet = Some(SheetSelector::parse(sheet));
+    }
+
+    let lines = TaskList::try_from_path(excel_path.to_str().unwrap(), &reading_options);
 
     if let Err(e) = lines {
         panic!("Failed to read your spreadsheet: {:?}", e)
     }
 
-    let mapping = TemplateMapping::from_file(template_path.to_str().unwrap());
+    let lines = lines.unwrap();
+    let invoices = mapping.apply_split(&lines);
 
-    if let Err(e) = mapping {
-        panic!("Failed to read your mapping configuration: {:?}", e);
+    if let Err(e) = invoices {
+        panic!("Could not apply template: {:?}", e);
     }
 
-    let items = mapping.unwrap().apply(&lines.unwrap());
+    let (invoices, summary) = invoices.unwrap();
 
-    if let Err(e) = items {
-        panic!("Could not apply template: {:?}", e);
+    print_filter_summary(&summary, mapping.filter());
+
+    let client = InvoiceShelfClient::new(app_config).with_token(session.token());
+    let variables: HashMap<String, String> = args.vars.iter().cloned().collect();
+    let today = chrono::Local::now().date_naive();
+
+    if let Some(per_item) = mapping.taxes_per_item() {
+        check_tax_per_item(&client, per_item);
+    }
+
+    // Every customer, tax and currency is looked up before anything is sent, so that a
+    // missing one does not leave the import half done.
+    let mut prepared = Vec::new();
+    // Customer billed when none is given, asked once for all invoices.
+    let mut default_customer: Option<Customer> = None;
+    // Customers to create once every lookup succeeded, by name.
+    let mut missing_customers: Vec<(String, ComputedCustomerFields)> = Vec::new();
+    // Fetched once, when the first invoice with taxes comes.
+    let mut tax_types: Vec<TaxType> = Vec::new();
+    // Fetched once, when the first invoice with a currency comes.
+    let mut currencies: Vec<Currency> = Vec::new();
+
+    for invoice_items in invoices {
+        let fields = mapping.invoice_fields(&lines, &invoice_items, &variables, today);
+
+        if let Err(e) = fields {
+            panic!("Could not compute the invoice fields: {:?}", e);
+        }
+
+        let fields = fields.unwrap();
+
+        let customer = match &fields.customer {
+            None => {
+                if let None = default_customer {
+                    default_customer = Some(choose_customer_prompt(&client));
+                }
+
+                PreparedCustomer::Found(default_customer.clone().unwrap())
+            }
+            Some(reference) => match (resolve_customer_prompt(&client, reference), reference) {
+                (Some(c), _) => PreparedCustomer::Found(c),
+                (None, CustomerRef::Id(id)) => {
+                    panic!("No customer on InvoiceShelf has the id or the name {}.", id)
+                }
+                (None, CustomerRef::Name(name)) => {
+                    if !missing_customers.iter().any(|(n, _)| n == name) {
+                        let customer_fields =
+                            mapping.customer_fields(&lines, &invoice_items, &variables, today);
+
+                        if let Err(e) = customer_fields {
+                            panic!("Could not compute the customer fields: {:?}", e);
+                        }
+
+                        let customer_fields = customer_fields.unwrap();
+
+                        // Without review, codes unknown to InvoiceShelf would only fail on
+                        // creation, once other customers already exist.
+                        if args.create_missing_customers {
+                            if let Err(message) = new_customer(&client, name, &customer_fields) {
+                                panic!("Could not create customer {}: {}", name, message);
+                            }
+                        }
+
+                        missing_customers.push((name.clone(), customer_fields));
+                    }
+
+                    PreparedCustomer::Missing(name.clone())
+                }
+            },
+        };
+
+        let mut tax_names: Vec<&String> = fields.taxes.iter().collect();
+        tax_names.extend(invoice_items.items.iter().flat_map(|i| &i.taxes));
+
+        if !tax_names.is_empty() && tax_types.is_empty() {
+            tax_types = fetch_tax_types(&client);
+        }
+
+        let invoice_taxes = find_tax_types(&tax_types, &fields.taxes);
+        let item_taxes: Vec<Vec<TaxType>> = invoice_items
+            .items
+            .iter()
+            .map(|i| find_tax_types(&tax_types, &i.taxes))
+            .collect();
+
+        let mut currency_id = None;
+
+        if let Some(code) = &fields.currency {
+            if currencies.is_empty() {
+                currencies = fetch_currencies(&client);
+            }
+
+            let currency = find_currency(&currencies, code);
+
+            if let None = currency {
+                panic!("No currency on InvoiceShelf has the code {}.", code);
+            }
+
+            currency_id = Some(currency.unwrap().id);
+        }
+
+        prepared.push(PreparedInvoice {
+            items: invoice_items.items,
+            fields,
+            customer,
+            invoice_taxes,
+            item_taxes,
+            currency_id,
+        });
+    }
+
+    check_fixed_numbers(&prepared);
+
+    let mut created_customers: HashMap<String, Customer> = HashMap::new();
+
+    for (name, customer_fields) in missing_customers {
+        let customer = create_customer_prompt(
+            &client,
+            &name,
+            customer_fields,
+            args.create_missing_customers,
+        );
+        created_customers.insert(name, customer);
+    }
+
+    // Numbers of the company's invoices, fetched once when a pattern has a sequence, and
+    // completed with those sent during this import.
+    let mut invoice_numbers: Option<Vec<String>> = None;
+
+    for p in prepared {
+        let customer = match p.customer {
+            PreparedCustomer::Found(c) => c,
+            PreparedCustomer::Missing(name) => created_customers[&name].clone(),
+        };
+        let mut fields = p.fields;
+        let number = invoice_number(&client, &fields, &mut invoice_numbers);
+
+        if number_is_taken(&client, &number) {
+            println!(
+                "Invoice number {} is already used, skipping the invoice for {}.",
+                number, customer.name
+            );
+            continue;
+        }
+
+        fields.number = Some(number.clone());
+
+        let mut invoice = Invoice::from_generated_items(p.items.clone(), &fields, customer.id)
+            .with_taxes(&p.invoice_taxes, &p.item_taxes);
+
+        if let Some(id) = p.currency_id {
+            invoice = invoice.with_currency(id);
+        }
+
+        if !preview_prompt(&fields, &customer.name, &p.items, &invoice) {
+            println!("Skipping the invoice for {}.", customer.name);
+            continue;
+        }
+
+        if submit_invoice(&client, &invoice) {
+            if let Some(numbers) = &mut invoice_numbers {
+                numbers.push(number);
+            }
+        }
+    }
+}
+
+/// Invoice whose customer, taxes and currency were all found, ready to be numbered and sent.
+struct PreparedInvoice {
+    items: Vec<ComputedMappingOutput>,
+    fields: ComputedInvoiceFields,
+    customer: PreparedCustomer,
+    invoice_taxes: Vec<TaxType>,
+    item_taxes: Vec<Vec<TaxType>>,
+    currency_id: Option<i64>,
+}
+
+/// Customer of a prepared invoice, either found on InvoiceShelf or still to be created.
+enum PreparedCustomer {
+    Found(Customer),
+    Missing(String),
+}
+
+pub fn fetch_currencies(client: &InvoiceShelfClient) -> Vec<Currency> {
+    let currencies = client.currencies();
+
+    if let Err(e) = currencies {
+        panic!(
+            "Something wrong happened while fetching currencies :  {:?}",
+            e
+        );
+    }
+
+    return currencies.unwrap();
+}
+
+pub fn find_currency<'a>(currencies: &'a [Currency], code: &str) -> Option<&'a Currency> {
+    return currencies
+        .iter()
+        .find(|c| c.code.eq_ignore_ascii_case(code));
+}
+
+fn fetch_tax_types(client: &InvoiceShelfClient) -> Vec<TaxType> {
+    let tax_types = client.tax_types();
+
+    if let Err(e) = tax_types {
+        panic!(
+            "Something wrong happened while fetching tax types :  {:?}",
+            e
+        );
+    }
+
+    return tax_types.unwrap();
+}
+
+/// Stops the import when the template sets taxes per item and the company's
+/// `tax_per_item` setting does not, or the other way round, as InvoiceShelf would then
+/// ignore them.
+fn check_tax_per_item(client: &InvoiceShelfClient, per_item: bool) {
+    let settings = client.company_settings(&["tax_per_item"]);
+
+    if let Err(e) = settings {
+        panic!(
+            "Something wrong happened while fetching the company settings :  {:?}",
+            e
+        );
+    }
+
+    let company_per_item = settings
+        .unwrap()
+        .get("tax_per_item")
+        .cloned()
+        .flatten()
+        .is_some_and(|s| s.eq_ignore_ascii_case("YES"));
+
+    if per_item && !company_per_item {
+        panic!(
+            "Your template taxes every item, but the company taxes whole invoices. Set \
+                taxes under [invoice] or enable taxes per item in InvoiceShelf."
+        );
+    }
+
+    if !per_item && company_per_item {
+        panic!(
+            "Your template taxes whole invoices, but the company taxes every item. Set \
+                taxes under [outputs] or disable taxes per item in InvoiceShelf."
+        );
+    }
+}
+
+/// Tax types named in the template, by name or else by percentage, as in `20%`. Each
+/// one may only be named once.
+fn find_tax_types(tax_types: &[TaxType], names: &[String]) -> Vec<TaxType> {
+    let mut found: Vec<TaxType> = Vec::new();
+
+    for name in names {
+        let by_name = tax_types
+            .iter()
+            .find(|t| t.name.trim().eq_ignore_ascii_case(name));
+        let percent = name.trim_end_matches('%').trim().replace(',', ".");
+        let by_percent: Vec<&TaxType> = match Decimal::from_str(&percent) {
+            Ok(p) => tax_types.iter().filter(|t| t.percent == p).collect(),
+            Err(_) => Vec::new(),
+        };
+
+        let tax_type = match (by_name, by_percent.as_slice()) {
+            (Some(t), _) => t,
+            (None, [t]) => *t,
+            (None, []) => panic!("No tax type on InvoiceShelf is named {}.", name),
+            (None, _) => panic!(
+                "Several tax types on InvoiceShelf are at {}, name the one to apply.",
+                name
+            ),
+        };
+
+        if found.iter().any(|t| t.id == tax_type.id) {
+            panic!(
+                "The tax type {} is named more than once in {}.",
+                tax_type.name,
+                names.join("; ")
+            );
+        }
+
+        found.push(tax_type.clone());
+    }
+
+    return found;
+}
+
+/// Number of the next invoice: the template's `number`, filled in when it is a pattern,
+/// or else the next one of the company's numbering settings. `existing` is filled with the
+/// numbers of the company's invoices the first time a sequence needs them.
+fn invoice_number(
+    client: &InvoiceShelfClient,
+    fields: &ComputedInvoiceFields,
+    existing: &mut Option<Vec<String>>,
+) -> String {
+    if let None = fields.number {
+        let number = client.next_number("invoice");
+
+        if let Err(e) = number {
+            panic!(
+                "Something wrong happened while fetching the next invoice number :  {:?}",
+                e
+            );
+        }
+
+        return number.unwrap();
     }
 
-    panic!("{:?}", items.unwrap());
-    // Send the request with the data
+    let pattern = NumberPattern::new(fields.number.as_ref().unwrap());
+
+    if !pattern.has_sequence() {
+        return pattern.format(fields.date, 0);
+    }
+
+    if let None = existing {
+        let invoices = client.invoices(None);
+
+        if let Err(e) = invoices {
+            panic!(
+                "Something wrong happened while fetching invoices :  {:?}",
+                e
+            );
+        }
+
+        *existing = Some(
+            invoices
+                .unwrap()
+                .into_iter()
+                .map(|i| i.invoice_number)
+                .collect(),
+        );
+    }
+
+    return pattern.next(fields.date, existing.as_ref().unwrap());
+}
+
+/// Fails when a number without `{seq}` comes out the same for several invoices of the
+/// import, as all but the first would then be refused.
+fn check_fixed_numbers(prepared: &[PreparedInvoice]) {
+    let mut numbers: Vec<String> = Vec::new();
+
+    for p in prepared {
+        if let None = p.fields.number {
+            continue;
+        }
+
+        let pattern = NumberPattern::new(p.fields.number.as_ref().unwrap());
+
+        if pattern.has_sequence() {
+            continue;
+        }
+
+        let number = pattern.format(p.fields.date, 0);
+
+        if numbers.contains(&number) {
+            panic!(
+                "Several invoices would be numbered {}. Add {{seq}} to the number of the \
+                template, or a placeholder telling the invoices apart.",
+                number
+            );
+        }
+
+        numbers.push(number);
+    }
+}
+
+fn number_is_taken(client: &InvoiceShelfClient, number: &str) -> bool {
+    let invoices = client.invoices(Some(number));
+
+    if let Err(e) = invoices {
+        panic!(
+            "Something wrong happened while looking for invoice {} :  {:?}",
+            number, e
+        );
+    }
+
+    return invoices.unwrap().iter().any(|i| i.invoice_number == number);
+}
+
+/// Shows the items of an invoice and asks whether to send it.
+fn preview_prompt(
+    fields: &ComputedInvoiceFields,
+    customer_name: &str,
+    items: &[ComputedMappingOutput],
+    invoice: &Invoice,
+) -> bool {
+    println!(
+        "Invoice {} for {}, dated {}, due {}:",
+        fields.number.as_deref().unwrap_or_default(),
+        customer_name,
+        fields.date,
+        fields.due_date
+    );
+
+    for i in items {
+        println!("  - {}: {} x {} = {}", i.name, i.quantity, i.price, i.total);
+
+        if !i.taxes.is_empty() {
+            println!("    Taxes: {}", i.taxes.join(", "));
+        }
+    }
+
+    if !fields.taxes.is_empty() {
+        println!("  Taxes: {}", fields.taxes.join(", "));
+    }
+
+    println!("  Sub-total: {}", invoice.sub_total());
+    println!("  Tax: {}", invoice.tax());
+    println!("  Total: {}", invoice.total());
+
+    let answer = Confirm::new("Send this invoice?")
+        .with_default(true)
+        .prompt();
+
+    if let Err(e) = answer {
+        panic!("Something wrong happened while interacting: {}", e);
+    }
+
+    return answer.unwrap();
+}
+
+/// Sends the invoice, returning whether InvoiceShelf created it.
+fn submit_invoice(client: &InvoiceShelfClient, invoice: &Invoice) -> bool {
+    match client.create_invoice(invoice) {
+        Ok(created) => {
+            println!(
+                "Invoice {} created successfully with id {} !",
+                created.invoice_number, created.id
+            );
+            return true;
+        }
+        Err(ApiRequestError::ValidationError(e)) => {
+            println!("InvoiceShelf rejected the invoice: {}", e.message);
+            print_validation_errors(&e);
+            return false;
+        }
+        Err(e) => {
+            panic!(
+                "Something wrong happened while sending your invoice :  {:?}",
+                e
+            )
+        }
+    }
+}
+
+pub fn print_validation_errors(errors: &ValidationErrors) {
+    let mut fields: Vec<_> = errors.errors.iter().collect();
+    fields.sort();
+
+    for (field, messages) in fields {
+        println!("  - {}: {}", field, messages.join(" "));
+    }
+}
+
+fn print_filter_summary(summary: &FilterSummary, filter: &RowFilter) {
+    println!(
+        "{} rows imported, {} filtered out.",
+        summary.kept,
+        summary.skipped()
+    );
+
+    let reasons = [
+        (
+            summary.not_matching,
+            format!(
+                "not matching \"{}\"",
+                filter.condition.as_deref().unwrap_or("")
+            ),
+        ),
+        (
+            summary.before_from,
+            format!("dated before {}", filter.from.unwrap_or_default()),
+        ),
+        (
+            summary.after_to,
+            format!("dated after {}", filter.to.unwrap_or_default()),
+        ),
+        (summary.undated, String::from("without a readable date")),
+    ];
+
+    for (count, reason) in reasons {
+        if count > 0 {
+            println!("  - {} {}", count, reason);
+        }
+    }
 }
@@ -2,7 +2,10 @@ use core::panic;
 
 use inquire::{Password, Text};
 
-use crate::{app_config::AppConfig, session::Session};
+use crate::{
+    app_config::AppConfig,
+    session::{Session, SessionResumingError},
+};
 
 pub fn login_prompt(username: &Option<String>, password: &Option<String>, config: &AppConfig) {
     let username_str;
@@ -16,7 +19,7 @@ pub fn login_prompt(username: &Option<String>, password: &Option<String>, config
 
         username_str = response.unwrap();
     } else {
-        username_str = String::from(username.clone().unwrap());
+        username_str = username.clone().unwrap();
     }
 
     let password_str;
@@ -30,7 +33,7 @@ pub fn login_prompt(username: &Option<String>, password: &Option<String>, config
 
         password_str = response.unwrap();
     } else {
-        password_str = String::from(password.clone().unwrap());
+        password_str = password.clone().unwrap();
     }
 
     let session = Session::new(&username_str, &password_str, config);
@@ -41,15 +44,9 @@ pub fn login_prompt(username: &Option<String>, password: &Option<String>, config
                 println!("Failed to login with message: {}", message);
                 return login_prompt(&None, &None, config);
             }
-            crate::session::SessionOpeningError::NetworkingError(e) => {
+            crate::session::SessionOpeningError::RequestError(e) => {
                 panic!(
-                    "Something wrong happened while contacting your sever :  {}",
-                    e
-                )
-            }
-            crate::session::SessionOpeningError::HttpError(e) => {
-                panic!(
-                    "Something wrong happened while contacting your sever :  {}",
+                    "Something wrong happened while contacting your sever :  {:?}",
                     e
                 )
             }
@@ -62,3 +59,34 @@ pub fn login_prompt(username: &Option<String>, password: &Option<String>, config
         }
     }
 }
+
+/// Returns a session the server accepts, asking the user to log in again when needed.
+pub fn resume_session_prompt(config: &AppConfig) -> Session {
+    let session = Session::resume(config);
+
+    match session {
+        Ok(s) => return s,
+        Err(SessionResumingError::ReadError(_)) => {
+            println!("It seems you are not logged in, please log in now.");
+        }
+        Err(SessionResumingError::InvalidToken) => {
+            println!("Your session has expired, please log in again.");
+        }
+        Err(SessionResumingError::RequestError(e)) => {
+            panic!(
+                "Something wrong happened while contacting your sever :  {:?}",
+                e
+            )
+        }
+    }
+
+    login_prompt(&None, &None, config);
+
+    let session = Session::resume(config);
+
+    if let Err(e) = session {
+        panic!("Could not resume the session you just opened: {:?}", e);
+    }
+
+    return session.unwrap();
+}
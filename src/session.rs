@@ -1,84 +1,65 @@
 use std::{fs, path::PathBuf};
 
-use reqwest::StatusCode;
+use crate::{
+    app_config::AppConfig,
+    http_client::{ApiRequestError, InvoiceShelfClient},
+};
 
-use crate::app_config::AppConfig;
-
-#[derive(serde::Deserialize)]
 pub struct Session {
     token: String,
 }
 
-#[derive(serde::Serialize)]
-struct LoginBody {
-    username: String,
-    password: String,
-    device_name: String,
-}
-
 #[derive(Debug)]
 pub enum SessionOpeningError {
-    NetworkingError(reqwest::Error),
+    RequestError(ApiRequestError),
     WrongCreds(String),
-    HttpError(String),
     WriteError(TokenWriteReadError),
 }
 
 #[derive(Debug)]
+#[allow(dead_code, clippy::enum_variant_names)]
 pub enum TokenWriteReadError {
     TokenPathResolveError,
     TokenFileCreationError(std::io::Error),
     TokenDirCreationError(std::io::Error),
+    TokenFileReadError(std::io::Error),
+}
+
+#[derive(Debug)]
+#[allow(dead_code)]
+pub enum SessionResumingError {
+    ReadError(TokenWriteReadError),
+    InvalidToken,
+    RequestError(ApiRequestError),
 }
 
 impl Session {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(
         username: &str,
         password: &str,
         config: &AppConfig,
     ) -> Result<(), SessionOpeningError> {
-        let http_client = reqwest::blocking::Client::new();
-
-        let resp = http_client
-            .post(String::from(&config.hostname) + "/api/v1/auth/login")
-            .header("Content-Type", "application/json")
-            .header("Accept", "application/json")
-            .header("company", "1")
-            .json(&LoginBody {
-                username: String::from(username),
-                password: String::from(password),
-                device_name: String::from("InvoiceShelfCli"),
-            })
-            .send();
-
-        if let Err(e) = resp {
-            return Err(SessionOpeningError::NetworkingError(e));
-        }
+        let token = InvoiceShelfClient::new(config).login(username, password);
 
-        let u_resp = resp.unwrap();
-
-        match u_resp.status() {
-            StatusCode::OK => {
+        match token {
+            Ok(token) => {
                 println!("Successfully Logged in !");
-                let parsed = u_resp.json::<Session>();
 
-                if let Err(_) = parsed {
-                    panic!("Failed to parse json response !!");
-                }
-
-                if let Err(e) = parsed.unwrap().write_token(&config.session_token_dir_path) {
+                if let Err(e) = (Session { token }).write_token(&config.session_token_dir_path) {
                     return Err(SessionOpeningError::WriteError(e));
                 }
 
                 return Ok(());
             }
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                return Err(SessionOpeningError::WrongCreds(u_resp.text().unwrap()))
+            Err(ApiRequestError::ValidationError(e)) => {
+                return Err(SessionOpeningError::WrongCreds(e.message))
             }
-            _ => return Err(SessionOpeningError::HttpError(u_resp.text().unwrap())),
+            Err(e) => return Err(SessionOpeningError::RequestError(e)),
         }
     }
 
+    #[allow(clippy::unnecessary_fallible_conversions)]
     fn write_token(&self, session_path: &str) -> Result<(), TokenWriteReadError> {
         let dir = PathBuf::try_from(session_path);
 
@@ -86,17 +67,80 @@ impl Session {
             return Err(TokenWriteReadError::TokenPathResolveError);
         }
 
-        let u_dir = dir.unwrap().pop();
+        let mut u_dir = dir.unwrap();
+        u_dir.pop();
 
-        if let Err(e) = fs::create_dir_all(u_dir.to_string()) {
+        if let Err(e) = fs::create_dir_all(&u_dir) {
             return Err(TokenWriteReadError::TokenDirCreationError(e));
         }
 
-        if let Err(e) = fs::write(session_path, self.token.clone().to_string()) {
+        if let Err(e) = fs::write(session_path, self.token.clone()) {
             return Err(TokenWriteReadError::TokenFileCreationError(e));
         }
         return Ok(());
     }
 
-    pub fn resume(session_path: &str) {}
+    pub fn read_token(session_path: &str) -> Result<Session, TokenWriteReadError> {
+        let contents = fs::read_to_string(session_path);
+
+        if let Err(e) = contents {
+            return Err(TokenWriteReadError::TokenFileReadError(e));
+        }
+
+        return Ok(Session {
+            token: contents.unwrap().trim().to_string(),
+        });
+    }
+
+    pub fn token(&self) -> &str {
+        return &self.token;
+    }
+
+    /// Reads the stored token and makes sure the server still accepts it.
+    pub fn resume(config: &AppConfig) -> Result<Session, SessionResumingError> {
+        let session = Self::read_token(&config.session_token_dir_path);
+
+        if let Err(e) = session {
+            return Err(SessionResumingError::ReadError(e));
+        }
+
+        let session = session.unwrap();
+
+        if session.token.is_empty() {
+            return Err(SessionResumingError::InvalidToken);
+        }
+
+        let check = InvoiceShelfClient::new(config)
+            .with_token(&session.token)
+            .check_auth();
+
+        match check {
+            Ok(true) => return Ok(session),
+            Ok(false) | Err(ApiRequestError::AuthError(_)) => {
+                return Err(SessionResumingError::InvalidToken)
+            }
+            Err(e) => return Err(SessionResumingError::RequestError(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_and_read_token() {
+        let mut path = std::env::temp_dir();
+        path.push("invoice_shelf_cli_test/nested/session");
+        let path = path.to_str().unwrap();
+
+        let session = Session {
+            token: String::from("1|abcdef"),
+        };
+        session.write_token(path).unwrap();
+
+        assert_eq!(Session::read_token(path).unwrap().token(), "1|abcdef");
+
+        fs::remove_file(path).unwrap();
+    }
 }
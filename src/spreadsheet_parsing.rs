@@ -1,14 +1,100 @@
+pub mod csv_reader;
+
 pub mod spreadsheet_data {
-    use calamine::{open_workbook, Data, Ods, OdsError, Range, Reader};
+    use calamine::{open_workbook_auto, Data, Range, Reader};
+    use chrono::{Duration, NaiveDate, NaiveDateTime, Timelike};
+    use regex::Regex;
+    use serde::Deserialize;
     use std::collections::HashMap;
 
+    use super::csv_reader::{self, CsvOptions};
+
     #[derive(Debug)]
+    #[allow(dead_code)]
     pub enum SpreadsheetReadingError {
-        HeadersError(OdsError),
+        HeadersError(calamine::Error),
         InvalidAttributes(String),
         NoFirstPageError,
-        ReadingError(OdsError),
+        ReadingError(calamine::Error),
         AccessError(String),
+        FileOpeningError(std::io::Error),
+        CsvError(csv::Error),
+        SheetNotFound {
+            requested: String,
+            available: Vec<String>,
+        },
+    }
+
+    /// Worksheet to read, either by name or by 0-based position.
+    #[derive(Debug, Clone, PartialEq, Deserialize)]
+    #[serde(untagged)]
+    pub enum SheetSelector {
+        Index(usize),
+        Name(String),
+    }
+
+    impl SheetSelector {
+        /// Numbers are read as positions, anything else as a sheet name.
+        pub fn parse(selector: &str) -> Self {
+            if let Ok(i) = selector.trim().parse::<usize>() {
+                return SheetSelector::Index(i);
+            }
+
+            return SheetSelector::Name(String::from(selector));
+        }
+
+        fn resolve(&self, names: &[String]) -> Option<String> {
+            match self {
+                SheetSelector::Index(i) => return names.get(*i).cloned(),
+                SheetSelector::Name(n) => return names.iter().find(|s| *s == n).cloned(),
+            }
+        }
+
+        #[allow(clippy::inherent_to_string)]
+        fn to_string(&self) -> String {
+            match self {
+                SheetSelector::Index(i) => return i.to_string(),
+                SheetSelector::Name(n) => return n.clone(),
+            }
+        }
+    }
+
+    /// Where the data of a sheet ends.
+    #[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum EndOfData {
+        /// Stop at the first empty row.
+        #[default]
+        EmptyRow,
+        /// Read up to the last used row, leaving empty rows out.
+        SkipEmpty,
+        /// Stop at the first row holding the sentinel value, leaving empty rows out.
+        Sentinel,
+        /// Read up to the last used row, empty rows included.
+        LastRow,
+    }
+
+    /// How rows are read, set under `[rows]` in the template.
+    #[derive(Debug, Clone, Default, Deserialize)]
+    pub struct RowsOptions {
+        #[serde(default)]
+        pub end: EndOfData,
+        /// Value ending the data when `end = "sentinel"`, such as "TOTAL".
+        pub sentinel: Option<String>,
+        /// Column to look for the sentinel in. Any column when missing.
+        pub sentinel_column: Option<String>,
+        /// Columns that may be empty. They are not looked at to tell whether a row is empty.
+        #[serde(default)]
+        pub optional_columns: Vec<String>,
+    }
+
+    /// Everything that changes how a file is turned into a `TaskList`.
+    #[derive(Debug, Default)]
+    pub struct ReadingOptions {
+        pub csv: CsvOptions,
+        pub rows: RowsOptions,
+        /// Defaults to the first worksheet.
+        pub sheet: Option<SheetSelector>,
     }
 
     #[derive(Debug)]
@@ -16,55 +102,369 @@ pub mod spreadsheet_data {
         String(String),
         Float(f64),
         Integer(i64),
+        Bool(bool),
+        Date(NaiveDateTime),
+        Duration(Duration),
         None,
     }
 
     impl Value {
+        pub fn is_empty(&self) -> bool {
+            match self {
+                Value::None => return true,
+                Value::String(s) => return s.trim().is_empty(),
+                _ => return false,
+            }
+        }
+
+        #[allow(clippy::inherent_to_string)]
         pub fn to_string(&self) -> String {
             match self {
                 Value::String(s) => s.clone(),
                 Value::Float(f) => f.to_string(),
                 Value::Integer(i) => i.to_string(),
+                Value::Bool(b) => b.to_string(),
+                Value::Date(d) => {
+                    if d.time() == chrono::NaiveTime::MIN {
+                        d.format("%Y-%m-%d").to_string()
+                    } else {
+                        d.format("%Y-%m-%d %H:%M:%S").to_string()
+                    }
+                }
+                Value::Duration(d) => {
+                    let seconds = d.num_seconds();
+                    let (hours, minutes, seconds) = (
+                        seconds / 3600,
+                        (seconds % 3600).abs() / 60,
+                        (seconds % 60).abs(),
+                    );
+
+                    if seconds == 0 {
+                        format!("{}:{:02}", hours, minutes)
+                    } else {
+                        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+                    }
+                }
                 Value::None => String::from(""),
             }
         }
+
+        /// Numeric form of the value: durations count in hours and booleans as 0 or 1.
+        pub fn as_number(&self) -> Option<f64> {
+            match self {
+                Value::Float(f) => return Some(*f),
+                Value::Integer(i) => return Some(*i as f64),
+                Value::Bool(b) => return Some(if *b { 1.0 } else { 0.0 }),
+                Value::Duration(d) => return Some(d.num_milliseconds() as f64 / 3_600_000.0),
+                Value::String(s) => return s.trim().parse::<f64>().ok(),
+                Value::Date(_) | Value::None => return None,
+            }
+        }
+
+        /// Reads the value as a date, from a date cell, text as read by [`parse_date`] or
+        /// a spreadsheet date serial, counted in days from 1899-12-30.
+        pub fn as_date(&self) -> Option<NaiveDateTime> {
+            match self {
+                Value::Date(d) => return Some(*d),
+                Value::String(s) => return parse_date(s),
+                Value::Float(_) | Value::Integer(_) => {
+                    let days = self.as_number().unwrap();
+
+                    // Up to 9999-12-31, as spreadsheets go.
+                    if !(0.0..2_958_466.0).contains(&days) {
+                        return None;
+                    }
+
+                    return NaiveDate::from_ymd_opt(1899, 12, 30)
+                        .and_then(|d| d.and_hms_opt(0, 0, 0))
+                        .map(|d| d + Duration::milliseconds((days * 86_400_000.0).round() as i64));
+                }
+                _ => return None,
+            }
+        }
+
+        /// Reads the value as a duration. Plain numbers are taken as hours.
+        pub fn as_duration(&self) -> Option<Duration> {
+            match self {
+                Value::Duration(d) => return Some(*d),
+                Value::String(s) => return parse_duration(s),
+                Value::Float(_) | Value::Integer(_) => {
+                    let hours = self.as_number().unwrap();
+                    return Some(Duration::milliseconds((hours * 3_600_000.0).round() as i64));
+                }
+                _ => return None,
+            }
+        }
+
+        /// Text to parse when the value feeds a numeric field.
+        pub fn to_number_string(&self) -> String {
+            match self {
+                Value::Bool(_) | Value::Duration(_) => {
+                    return self.as_number().unwrap().to_string()
+                }
+                _ => return self.to_string(),
+            }
+        }
     }
 
     impl Clone for Value {
         fn clone(&self) -> Self {
             match self {
                 Value::String(s) => Value::String(s.clone()),
-                Value::Float(f) => Value::Float(f.clone()),
-                Value::Integer(i) => Value::Integer(i.clone()),
+                Value::Float(f) => Value::Float(*f),
+                Value::Integer(i) => Value::Integer(*i),
+                Value::Bool(b) => Value::Bool(*b),
+                Value::Date(d) => Value::Date(*d),
+                Value::Duration(d) => Value::Duration(*d),
                 Value::None => Value::None,
             }
         }
     }
 
+    /// Reads an ISO 8601 duration such as `PT1H30M` or `P1DT2H`.
+    pub fn parse_iso_duration(text: &str) -> Option<Duration> {
+        let text = text.trim();
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(t) => (true, t),
+            None => (false, text),
+        };
+
+        let text = text.strip_prefix('P');
+
+        if let None = text {
+            return None;
+        }
+
+        let mut total_ms: f64 = 0.0;
+        let mut number = String::new();
+        let mut in_time = false;
+        let mut found_any = false;
+
+        for c in text.unwrap().chars() {
+            if c.is_ascii_digit() || c == '.' || c == ',' {
+                number.push(if c == ',' { '.' } else { c });
+                continue;
+            }
+
+            if c == 'T' && !in_time && number.is_empty() {
+                in_time = true;
+                continue;
+            }
+
+            let value = number.parse::<f64>();
+
+            if let Err(_) = value {
+                return None;
+            }
+
+            let unit_ms = match (c, in_time) {
+                ('W', false) => 7.0 * 86_400_000.0,
+                ('D', false) => 86_400_000.0,
+                ('H', true) => 3_600_000.0,
+                ('M', true) => 60_000.0,
+                ('S', true) => 1_000.0,
+                _ => return None,
+            };
+
+            total_ms += value.unwrap() * unit_ms;
+            number.clear();
+            found_any = true;
+        }
+
+        if !found_any || !number.is_empty() {
+            return None;
+        }
+
+        let duration = Duration::milliseconds(total_ms.round() as i64);
+
+        return Some(if negative { -duration } else { duration });
+    }
+
+    /// Reads the duration forms found in timesheets: `1:30`, `1:30:15`, `1h30`, `1.5h`,
+    /// `90 min`, `2 hours`, `45s` and ISO 8601 (`PT1H30M`).
+    pub fn parse_duration(text: &str) -> Option<Duration> {
+        let text = text.trim();
+
+        if text.starts_with('P') || text.starts_with("-P") {
+            return parse_iso_duration(text);
+        }
+
+        let number = |s: &str| s.replace(',', ".").parse::<f64>().unwrap_or(0.0);
+
+        let clock = Regex::new(r"^(\d+):([0-5]?\d)(?::([0-5]?\d(?:[.,]\d+)?))?$").unwrap();
+
+        if let Some(c) = clock.captures(text) {
+            let seconds = number(&c[1]) * 3600.0
+                + number(&c[2]) * 60.0
+                + c.get(3).map(|s| number(s.as_str())).unwrap_or(0.0);
+
+            return Some(Duration::milliseconds((seconds * 1000.0).round() as i64));
+        }
+
+        // "1h30": minutes written without their unit.
+        let short = Regex::new(r"(?i)^(\d+)\s*h\s*([0-5]?\d)$").unwrap();
+
+        if let Some(c) = short.captures(text) {
+            return Some(Duration::minutes(
+                c[1].parse::<i64>().unwrap_or(0) * 60 + c[2].parse::<i64>().unwrap_or(0),
+            ));
+        }
+
+        let units = Regex::new(
+            r"(?ix)^
+            (?: (?P<h>\d+(?:[.,]\d+)?) \s* h(?:ours?|rs?)? )? \s*
+            (?: (?P<m>\d+(?:[.,]\d+)?) \s* m(?:in(?:utes?|s)?|n)? )? \s*
+            (?: (?P<s>\d+(?:[.,]\d+)?) \s* s(?:ec(?:onds?|s)?)? )?
+            $",
+        )
+        .unwrap();
+
+        let c = units.captures(text);
+
+        if let None = c {
+            return None;
+        }
+
+        let c = c.unwrap();
+
+        if c.name("h").is_none() && c.name("m").is_none() && c.name("s").is_none() {
+            return None;
+        }
+
+        let seconds = c.name("h").map(|h| number(h.as_str())).unwrap_or(0.0) * 3600.0
+            + c.name("m").map(|m| number(m.as_str())).unwrap_or(0.0) * 60.0
+            + c.name("s").map(|s| number(s.as_str())).unwrap_or(0.0);
+
+        return Some(Duration::milliseconds((seconds * 1000.0).round() as i64));
+    }
+
+    /// Reads an ISO 8601 date, with or without a time.
+    pub fn parse_iso_date(text: &str) -> Option<NaiveDateTime> {
+        let text = text.trim();
+
+        if let Ok(dt) = text.parse::<NaiveDateTime>() {
+            return Some(dt.with_nanosecond(0).unwrap_or(dt));
+        }
+
+        if let Ok(d) = text.parse::<NaiveDate>() {
+            return d.and_hms_opt(0, 0, 0);
+        }
+
+        return None;
+    }
+
+    /// Reads ISO 8601 dates, then day-first dates as sheets display them: `31/10/2024`,
+    /// `31.10.2024` or `31-10-2024`, with 2 or 4-digit years and an optional `HH:MM[:SS]`.
+    pub fn parse_date(text: &str) -> Option<NaiveDateTime> {
+        if let Some(d) = parse_iso_date(text) {
+            return Some(d);
+        }
+
+        let day_first = Regex::new(
+            r"^(\d{1,2})([/.-])(\d{1,2})([/.-])(\d{2}|\d{4})(?:[ T](\d{1,2}):(\d{2})(?::(\d{2}))?)?$",
+        )
+        .unwrap();
+        let captures = day_first.captures(text.trim());
+
+        if let None = captures {
+            return None;
+        }
+
+        let c = captures.unwrap();
+
+        if c[2] != c[4] {
+            return None;
+        }
+
+        let number = |i: usize| c.get(i).map_or(0, |m| m.as_str().parse::<u32>().unwrap());
+        let year = match c[5].len() {
+            2 => 2000 + number(5) as i32,
+            _ => number(5) as i32,
+        };
+
+        return NaiveDate::from_ymd_opt(year, number(3), number(1))
+            .and_then(|d| d.and_hms_opt(number(6), number(7), number(8)));
+    }
+
+    /// One line of data, along with the line it was read from.
+    #[derive(Debug, Clone)]
+    pub struct TaskRow {
+        /// 1-based row number, as displayed by the spreadsheet software.
+        pub row_number: usize,
+        values: Vec<Value>,
+    }
+
+    impl TaskRow {
+        pub fn new(row_number: usize, values: Vec<Value>) -> Self {
+            return Self { row_number, values };
+        }
+
+        /// Whether every cell is empty, leaving out the `ignored` columns.
+        fn is_empty(&self, ignored: &[usize]) -> bool {
+            return self
+                .values
+                .iter()
+                .enumerate()
+                .all(|(i, v)| ignored.contains(&i) || v.is_empty());
+        }
+    }
+
     #[derive(Debug)]
-    pub struct TaskList(HashMap<String, Vec<Value>>);
+    pub struct TaskList {
+        headers: HashMap<String, usize>,
+        rows: Vec<TaskRow>,
+    }
 
     impl TaskList {
-        pub fn from(map: HashMap<String, Vec<Value>>) -> Self {
-            return Self(map);
+        pub fn from(headers: Vec<String>, rows: Vec<TaskRow>) -> Self {
+            let headers = headers
+                .into_iter()
+                .enumerate()
+                .filter(|(_, h)| !h.is_empty())
+                .map(|(i, h)| (h, i))
+                .collect();
+
+            return Self { headers, rows };
         }
 
-        pub fn try_from_path(path: &str) -> Result<Self, SpreadsheetReadingError> {
-            let mut tasks = HashMap::<String, Vec<Value>>::new();
+        pub fn try_from_path(
+            path: &str,
+            options: &ReadingOptions,
+        ) -> Result<Self, SpreadsheetReadingError> {
+            if csv_reader::is_csv_path(path) {
+                let cells = csv_reader::read_csv(path, &options.csv);
 
-            let spreadsheet: Result<Ods<_>, _> = open_workbook(path);
+                if let Err(e) = cells {
+                    return Err(e);
+                }
+
+                return Self::from_range(&cells.unwrap(), options.csv.header_row, &options.rows);
+            }
+            // Picks the reader from the extension, or sniffs the content when there is none.
+            let spreadsheet = open_workbook_auto(path);
 
             if let Err(e) = spreadsheet {
                 return Err(SpreadsheetReadingError::ReadingError(e));
             }
 
-            let range = spreadsheet.unwrap().worksheet_range_at(0);
+            let mut spreadsheet = spreadsheet.unwrap();
+            let names = spreadsheet.sheet_names();
 
-            if let None = range {
+            if names.is_empty() {
                 return Err(SpreadsheetReadingError::NoFirstPageError);
             }
 
-            let cells = range.unwrap();
+            let selector = options.sheet.clone().unwrap_or(SheetSelector::Index(0));
+            let sheet_name = selector.resolve(&names);
+
+            if let None = sheet_name {
+                return Err(SpreadsheetReadingError::SheetNotFound {
+                    requested: selector.to_string(),
+                    available: names,
+                });
+            }
+
+            let cells = spreadsheet.worksheet_range(&sheet_name.unwrap());
 
             if let Err(e) = cells {
                 return Err(SpreadsheetReadingError::HeadersError(e));
@@ -72,96 +472,437 @@ pub mod spreadsheet_data {
 
             let cells = cells.unwrap();
 
-            let width: u32 = cells.width().try_into().unwrap();
+            // Ranges start at the first used cell, which is not always A1.
+            let header_row = cells.start().map(|(y, _)| y as usize + 1).unwrap_or(1);
 
-            for x in 0..width {
-                let column = Self::read_column(&cells, x);
+            return Self::from_range(&cells, header_row, &options.rows);
+        }
 
-                if let Some(c) = column {
-                    tasks.insert(c.0, c.1);
+        /// Reads the first line of `cells` as headers and the following ones as rows,
+        /// until the end of the data as told by `options`.
+        pub fn from_range(
+            cells: &Range<Data>,
+            header_row: usize,
+            options: &RowsOptions,
+        ) -> Result<Self, SpreadsheetReadingError> {
+            let headers: Vec<String> = (0..cells.width())
+                .map(|x| match cells.get((0, x)) {
+                    Some(h) => h.to_string().trim().to_string(),
+                    None => String::new(),
+                })
+                .collect();
+
+            let optional: Vec<usize> = headers
+                .iter()
+                .enumerate()
+                .filter(|(_, h)| options.optional_columns.contains(h))
+                .map(|(i, _)| i)
+                .collect();
+
+            let mut sentinel_column = None;
+
+            if options.end == EndOfData::Sentinel {
+                if let None = options.sentinel {
+                    return Err(SpreadsheetReadingError::InvalidAttributes(String::from(
+                        "A sentinel value is needed to end the data on a sentinel.",
+                    )));
                 }
-            }
 
-            return Ok(TaskList(tasks));
-        }
+                if let Some(c) = &options.sentinel_column {
+                    sentinel_column = headers.iter().position(|h| h == c);
 
-        fn spreadsheet_to_value(d: &Data) -> Value {
-            match d {
-                Data::String(s) => Value::String(String::from(s)),
-                Data::Int(i) => Value::Integer(i.clone()),
-                Data::Float(f) => Value::Float(f.clone()),
-                Data::Bool(b) => Value::String(b.to_string()),
-                Data::DateTime(dt) => Value::String(dt.to_string()),
-                Data::DateTimeIso(dti) => Value::String(dti.to_string()),
-                Data::DurationIso(di) => Value::String(di.to_string()),
-                Data::Error(_) => Value::None,
-                Data::Empty => Value::None,
+                    if let None = sentinel_column {
+                        return Err(SpreadsheetReadingError::AccessError(
+                            String::from("Spreadsheet has no data under the name: ") + c,
+                        ));
+                    }
+                }
             }
-        }
 
-        fn read_column(range: &Range<Data>, column: u32) -> Option<(String, Vec<Value>)> {
-            let header = range.get((0, column.try_into().unwrap()));
+            let mut rows = Vec::<TaskRow>::new();
 
-            if let None = header {
-                return None;
-            }
+            for line in 1..cells.height() {
+                let values: Vec<Value> = (0..cells.width())
+                    .map(|x| match cells.get((line, x)) {
+                        Some(v) => Self::spreadsheet_to_value(v),
+                        None => Value::None,
+                    })
+                    .collect();
 
-            let mut values: Vec<Value> = Vec::new();
+                if let Some(sentinel) = &options.sentinel {
+                    let is_sentinel = |v: &Value| v.to_string().trim() == sentinel.trim();
 
-            for line in 1..range.height() {
-                let val = range.get((line, column.try_into().unwrap()));
+                    let found = match sentinel_column {
+                        Some(c) => values.get(c).is_some_and(is_sentinel),
+                        None => values.iter().any(is_sentinel),
+                    };
 
-                if let Some(v) = val {
-                    let converted = Self::spreadsheet_to_value(v);
-                    if let Value::None = converted {
-                        println!("Breaking at {:?} - {:?}", line, column);
+                    if options.end == EndOfData::Sentinel && found {
                         break;
                     }
-                    values.push(converted);
-                } else {
-                    values.push(Value::String(String::from("")));
                 }
+
+                let row = TaskRow::new(header_row + line, values);
+
+                if row.is_empty(&optional) {
+                    match options.end {
+                        EndOfData::EmptyRow => break,
+                        EndOfData::SkipEmpty | EndOfData::Sentinel => continue,
+                        EndOfData::LastRow => {}
+                    }
+                }
+
+                rows.push(row);
             }
 
-            return Some((String::from(header.unwrap().to_string()), values));
-        }
+            if options.end == EndOfData::LastRow {
+                // Ranges can run past the data when formatting was applied to empty cells.
+                while rows.last().is_some_and(|r| r.is_empty(&[])) {
+                    rows.pop();
+                }
+            }
 
-        pub fn len(&self) -> usize {
-            let tasks = &self.0;
+            return Ok(Self::from(headers, rows));
+        }
 
-            let mut len = std::usize::MAX;
-            for v in tasks.values() {
-                if len > v.len() {
-                    len = v.len();
+        fn spreadsheet_to_value(d: &Data) -> Value {
+            match d {
+                Data::String(s) => Value::String(String::from(s)),
+                Data::Int(i) => Value::Integer(*i),
+                Data::Float(f) => Value::Float(*f),
+                Data::Bool(b) => Value::Bool(*b),
+                Data::DateTime(dt) => {
+                    let converted = if dt.is_duration() {
+                        dt.as_duration().map(Value::Duration)
+                    } else {
+                        dt.as_datetime().map(Value::Date)
+                    };
+
+                    converted.unwrap_or(Value::Float(dt.as_f64()))
                 }
+                Data::DateTimeIso(dti) => match parse_iso_date(dti) {
+                    Some(d) => Value::Date(d),
+                    None => Value::String(dti.to_string()),
+                },
+                Data::DurationIso(di) => match parse_iso_duration(di) {
+                    Some(d) => Value::Duration(d),
+                    None => Value::String(di.to_string()),
+                },
+                Data::Error(_) => Value::None,
+                Data::Empty => Value::None,
             }
+        }
+
+        pub fn len(&self) -> usize {
+            return self.rows.len();
+        }
 
-            return len;
+        pub fn headers(&self) -> Vec<&String> {
+            let mut headers: Vec<(&String, &usize)> = self.headers.iter().collect();
+            headers.sort_by_key(|(_, i)| **i);
+
+            return headers.into_iter().map(|(h, _)| h).collect();
+        }
+
+        /// Spreadsheet row number of the row at `index`.
+        pub fn row_number(&self, index: usize) -> Option<usize> {
+            return self.rows.get(index).map(|r| r.row_number);
         }
 
         pub fn get(&self, header: &str, index: usize) -> Result<Value, SpreadsheetReadingError> {
-            if let Some(values) = self.0.get(header) {
-                if index > values.len() {
-                    return Err(SpreadsheetReadingError::AccessError(String::from(
-                        "Trying to read out of bounds of the spreadsheet's data.",
-                    )));
-                }
+            let column = self.headers.get(header);
 
-                return Ok(values[index].clone());
+            if let None = column {
+                return Err(SpreadsheetReadingError::AccessError(
+                    String::from("Spreadsheet has no data under the name: ") + header,
+                ));
             }
 
-            return Err(SpreadsheetReadingError::AccessError(
-                String::from("Spreadsheet has no data under the name: ") + header,
-            ));
+            let row = self.rows.get(index);
+
+            if let None = row {
+                return Err(SpreadsheetReadingError::AccessError(String::from(
+                    "Trying to read out of bounds of the spreadsheet's data.",
+                )));
+            }
+
+            // Rows shorter than the header line hold empty cells.
+            return Ok(row
+                .unwrap()
+                .values
+                .get(*column.unwrap())
+                .cloned()
+                .unwrap_or(Value::None));
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use calamine::{Data, Range};
+
+    use super::spreadsheet_data::*;
 
     #[test]
     fn placeholder() {
         assert_eq!(2, 1 + 1);
     }
+
+    #[test]
+    fn read_ods() {
+        let tasks = TaskList::try_from_path("test.ods", &ReadingOptions::default()).unwrap();
+
+        assert_eq!(tasks.get("Tâche", 0).unwrap().to_string(), "Task 1");
+        assert_eq!(tasks.get("Coût", 1).unwrap().to_string(), "10.75");
+    }
+
+    #[test]
+    fn typed_values() {
+        let tasks = TaskList::try_from_path("test.ods", &ReadingOptions::default()).unwrap();
+
+        let duration = tasks.get("Durée", 2).unwrap();
+        assert!(matches!(duration, Value::Duration(_)));
+        assert_eq!(duration.to_string(), "1:15");
+        assert_eq!(duration.as_number(), Some(1.25));
+    }
+
+    #[test]
+    fn dates() {
+        let date = |v: Value| v.as_date().map(|d| d.to_string());
+        let text = |s: &str| date(Value::String(String::from(s)));
+
+        assert_eq!(
+            text("2024-10-31"),
+            Some(String::from("2024-10-31 00:00:00"))
+        );
+        assert_eq!(
+            text(" 31/10/2024 "),
+            Some(String::from("2024-10-31 00:00:00"))
+        );
+        assert_eq!(text("1.2.24"), Some(String::from("2024-02-01 00:00:00")));
+        assert_eq!(
+            text("31-10-2024 9:05"),
+            Some(String::from("2024-10-31 09:05:00"))
+        );
+        assert_eq!(text("31/10-2024"), None);
+        assert_eq!(text("10/31/2024"), None);
+        assert_eq!(text("Monday"), None);
+
+        assert_eq!(
+            date(Value::Integer(45596)),
+            Some(String::from("2024-10-31 00:00:00"))
+        );
+        assert_eq!(
+            date(Value::Float(45596.5)),
+            Some(String::from("2024-10-31 12:00:00"))
+        );
+        assert_eq!(date(Value::Integer(-1)), None);
+        assert_eq!(date(Value::Float(f64::NAN)), None);
+        assert_eq!(date(Value::Bool(true)), None);
+    }
+
+    #[test]
+    fn iso_durations() {
+        let minutes = |s: &str| parse_iso_duration(s).map(|d| d.num_minutes());
+
+        assert_eq!(minutes("PT1H30M"), Some(90));
+        assert_eq!(minutes("PT01H15M00S"), Some(75));
+        assert_eq!(minutes("P1DT2H"), Some(26 * 60));
+        assert_eq!(minutes("PT0.5H"), Some(30));
+        assert_eq!(minutes("-PT10M"), Some(-10));
+        assert_eq!(minutes("PT"), None);
+        assert_eq!(minutes("P1H"), None);
+        assert_eq!(minutes("1:30"), None);
+    }
+
+    #[test]
+    fn durations() {
+        let minutes = |s: &str| parse_duration(s).map(|d| d.num_minutes());
+
+        assert_eq!(minutes("1:30"), Some(90));
+        assert_eq!(minutes("12:05:00"), Some(725));
+        assert_eq!(minutes("1h30"), Some(90));
+        assert_eq!(minutes("1 h 30 min"), Some(90));
+        assert_eq!(minutes("1H"), Some(60));
+        assert_eq!(minutes("1.5h"), Some(90));
+        assert_eq!(minutes("1,5 hours"), Some(90));
+        assert_eq!(minutes("90 min"), Some(90));
+        assert_eq!(minutes("90mn"), Some(90));
+        assert_eq!(minutes("2 hours"), Some(120));
+        assert_eq!(minutes("PT1H30M"), Some(90));
+        assert_eq!(parse_duration("45s").map(|d| d.num_seconds()), Some(45));
+
+        assert_eq!(minutes("90"), None);
+        assert_eq!(minutes(""), None);
+        assert_eq!(minutes("soon"), None);
+        assert_eq!(minutes("1:75"), None);
+
+        assert_eq!(
+            Value::Float(1.25).as_duration().map(|d| d.num_minutes()),
+            Some(75)
+        );
+    }
+
+    #[test]
+    fn value_display() {
+        let date = parse_iso_date("2024-10-01").unwrap();
+        assert_eq!(Value::Date(date).to_string(), "2024-10-01");
+
+        let datetime = parse_iso_date("2024-10-01T09:30:00").unwrap();
+        assert_eq!(Value::Date(datetime).to_string(), "2024-10-01 09:30:00");
+
+        let duration = parse_iso_duration("PT26H05M30S").unwrap();
+        assert_eq!(Value::Duration(duration).to_string(), "26:05:30");
+
+        assert_eq!(Value::Bool(true).to_number_string(), "1");
+    }
+
+    #[test]
+    fn keeps_row_numbers() {
+        let tasks = TaskList::try_from_path("test.ods", &ReadingOptions::default()).unwrap();
+
+        assert_eq!(tasks.row_number(0), Some(2));
+        assert_eq!(tasks.row_number(21), Some(23));
+        assert_eq!(tasks.get("Tâche", 21).unwrap().to_string(), "Task 22");
+    }
+
+    #[test]
+    fn ragged_rows() {
+        let tasks = TaskList::from(
+            vec![String::from("a"), String::from("b")],
+            vec![
+                TaskRow::new(2, vec![Value::Integer(1)]),
+                TaskRow::new(3, vec![Value::Integer(2), Value::Integer(3)]),
+            ],
+        );
+
+        assert_eq!(tasks.len(), 2);
+        assert!(matches!(tasks.get("b", 0).unwrap(), Value::None));
+        assert!(matches!(tasks.get("b", 1).unwrap(), Value::Integer(3)));
+        assert!(tasks.get("b", 2).is_err());
+        assert!(tasks.get("c", 0).is_err());
+    }
+
+    fn spacer_range() -> Range<Data> {
+        let mut range = Range::new((0, 0), (6, 1));
+        let cells = [
+            ["Task", "Comment"],
+            ["a", ""],
+            ["", "spacer only"],
+            ["b", "c"],
+            ["TOTAL", ""],
+            ["", ""],
+            ["d", ""],
+        ];
+
+        for (y, row) in cells.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                if !cell.is_empty() {
+                    range.set_value((y as u32, x as u32), Data::String(cell.to_string()));
+                }
+            }
+        }
+
+        return range;
+    }
+
+    fn read_tasks(options: RowsOptions) -> Vec<String> {
+        let tasks = TaskList::from_range(&spacer_range(), 1, &options).unwrap();
+
+        return (0..tasks.len())
+            .map(|i| tasks.get("Task", i).unwrap().to_string())
+            .collect();
+    }
+
+    #[test]
+    fn end_of_data_policies() {
+        let optional = vec![String::from("Comment")];
+
+        let stop = read_tasks(RowsOptions {
+            optional_columns: optional.clone(),
+            ..RowsOptions::default()
+        });
+        assert_eq!(stop, vec!["a"]);
+
+        let skip = read_tasks(RowsOptions {
+            end: EndOfData::SkipEmpty,
+            optional_columns: optional.clone(),
+            ..RowsOptions::default()
+        });
+        assert_eq!(skip, vec!["a", "b", "TOTAL", "d"]);
+
+        let sentinel = read_tasks(RowsOptions {
+            end: EndOfData::Sentinel,
+            sentinel: Some(String::from("TOTAL")),
+            sentinel_column: Some(String::from("Task")),
+            optional_columns: optional.clone(),
+        });
+        assert_eq!(sentinel, vec!["a", "b"]);
+
+        let last = read_tasks(RowsOptions {
+            end: EndOfData::LastRow,
+            ..RowsOptions::default()
+        });
+        assert_eq!(last, vec!["a", "", "b", "TOTAL", "", "d"]);
+
+        // Without optional columns, the comment keeps the spacer row alive.
+        let strict = read_tasks(RowsOptions::default());
+        assert_eq!(strict, vec!["a", "", "b", "TOTAL"]);
+    }
+
+    #[test]
+    fn sentinel_needs_a_value() {
+        let options = RowsOptions {
+            end: EndOfData::Sentinel,
+            ..RowsOptions::default()
+        };
+
+        assert!(matches!(
+            TaskList::from_range(&spacer_range(), 1, &options),
+            Err(SpreadsheetReadingError::InvalidAttributes(_))
+        ));
+    }
+
+    #[test]
+    fn read_missing_sheet() {
+        let options = ReadingOptions {
+            sheet: Some(SheetSelector::Name(String::from("Summary"))),
+            ..ReadingOptions::default()
+        };
+
+        let tasks = TaskList::try_from_path("test.ods", &options);
+
+        if let Err(SpreadsheetReadingError::SheetNotFound {
+            requested,
+            available,
+        }) = tasks
+        {
+            assert_eq!(requested, "Summary");
+            assert!(!available.is_empty());
+        } else {
+            panic!("Expected a SheetNotFound error, got {:?}", tasks);
+        }
+    }
+
+    #[test]
+    fn read_sheet_by_index() {
+        let options = ReadingOptions {
+            sheet: Some(SheetSelector::parse("0")),
+            ..ReadingOptions::default()
+        };
+
+        let tasks = TaskList::try_from_path("test.ods", &options).unwrap();
+
+        assert_eq!(tasks.get("Tâche", 0).unwrap().to_string(), "Task 1");
+    }
+
+    #[test]
+    fn read_unknown_format() {
+        let tasks = TaskList::try_from_path("Cargo.toml", &ReadingOptions::default());
+
+        assert!(matches!(
+            tasks,
+            Err(SpreadsheetReadingError::ReadingError(_))
+        ));
+    }
 }
@@ -0,0 +1,318 @@
+use std::{fs, sync::OnceLock};
+
+use calamine::{Data, Range};
+use encoding_rs::Encoding;
+use regex::Regex;
+use serde::Deserialize;
+
+use super::spreadsheet_data::{self, SpreadsheetReadingError};
+
+/// How to read a CSV/TSV file, set under `[csv]` in the template.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CsvOptions {
+    /// Defaults to a tab for `.tsv` files and a comma otherwise.
+    pub delimiter: Option<char>,
+    #[serde(default = "CsvOptions::default_quote")]
+    pub quote: char,
+    /// Any WHATWG label, such as `utf-8`, `latin1` or `windows-1252`.
+    #[serde(default = "CsvOptions::default_encoding")]
+    pub encoding: String,
+    /// 1-based line holding the column names. Lines above it are ignored.
+    #[serde(default = "CsvOptions::default_header_row")]
+    pub header_row: usize,
+    /// `.` or `,`. Numbers using the other one, such as `1,234` by default, stay text.
+    #[serde(default = "CsvOptions::default_decimal_separator")]
+    pub decimal_separator: char,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        return Self {
+            delimiter: None,
+            quote: Self::default_quote(),
+            encoding: Self::default_encoding(),
+            header_row: Self::default_header_row(),
+            decimal_separator: Self::default_decimal_separator(),
+        };
+    }
+}
+
+impl CsvOptions {
+    fn default_quote() -> char {
+        return '"';
+    }
+
+    fn default_encoding() -> String {
+        return String::from("utf-8");
+    }
+
+    fn default_header_row() -> usize {
+        return 1;
+    }
+
+    fn default_decimal_separator() -> char {
+        return '.';
+    }
+}
+
+pub fn is_csv_path(path: &str) -> bool {
+    let lower = path.to_lowercase();
+    return lower.ends_with(".csv") || lower.ends_with(".tsv") || lower.ends_with(".txt");
+}
+
+fn as_byte(c: char, what: &str) -> Result<u8, SpreadsheetReadingError> {
+    if !c.is_ascii() {
+        return Err(SpreadsheetReadingError::InvalidAttributes(format!(
+            "The CSV {} must be an ASCII character, got '{}'",
+            what, c
+        )));
+    }
+
+    return Ok(c as u8);
+}
+
+/// Numbers as a spreadsheet would read them: an optional sign, digits, then decimals
+/// after a dot or, as French exports write them, a comma. Digits after a leading zero
+/// make a code such as `007`, kept as text.
+fn number_regex(decimal_comma: bool) -> &'static Regex {
+    static DOT: OnceLock<Regex> = OnceLock::new();
+    static COMMA: OnceLock<Regex> = OnceLock::new();
+
+    if decimal_comma {
+        return COMMA
+            .get_or_init(|| Regex::new(r"^-?(?:0|[1-9]\d*)(?:,\d+)?(?:[eE][+-]?\d+)?$").unwrap());
+    }
+
+    return DOT
+        .get_or_init(|| Regex::new(r"^-?(?:0|[1-9]\d*)(?:\.\d+)?(?:[eE][+-]?\d+)?$").unwrap());
+}
+
+/// Types a raw CSV cell the way a spreadsheet would have, trimmed.
+fn csv_to_data(cell: &str, decimal_separator: char) -> Data {
+    let trimmed = cell.trim();
+
+    if trimmed.is_empty() {
+        return Data::Empty;
+    }
+
+    if number_regex(decimal_separator == ',').is_match(trimmed) {
+        if let Ok(i) = trimmed.parse::<i64>() {
+            return Data::Int(i);
+        }
+
+        if let Ok(f) = trimmed.replace(',', ".").parse::<f64>() {
+            if f.is_finite() {
+                return Data::Float(f);
+            }
+        }
+    }
+
+    if trimmed.eq_ignore_ascii_case("true") || trimmed.eq_ignore_ascii_case("false") {
+        return Data::Bool(trimmed.eq_ignore_ascii_case("true"));
+    }
+
+    if spreadsheet_data::parse_iso_date(trimmed).is_some() {
+        return Data::DateTimeIso(String::from(trimmed));
+    }
+
+    if spreadsheet_data::parse_iso_duration(trimmed).is_some() {
+        return Data::DurationIso(String::from(trimmed));
+    }
+
+    return Data::String(String::from(trimmed));
+}
+
+/// Reads a CSV/TSV file into the same kind of range calamine gives for a worksheet.
+pub fn read_csv(path: &str, options: &CsvOptions) -> Result<Range<Data>, SpreadsheetReadingError> {
+    let bytes = fs::read(path);
+
+    if let Err(e) = bytes {
+        return Err(SpreadsheetReadingError::FileOpeningError(e));
+    }
+
+    let encoding = Encoding::for_label(options.encoding.as_bytes());
+
+    if let None = encoding {
+        return Err(SpreadsheetReadingError::InvalidAttributes(
+            String::from("Unknown encoding: ") + &options.encoding,
+        ));
+    }
+
+    let bytes = bytes.unwrap();
+    let (text, _, _) = encoding.unwrap().decode(&bytes);
+
+    let delimiter = match options.delimiter {
+        Some(d) => d,
+        None if path.to_lowercase().ends_with(".tsv") => '\t',
+        None => ',',
+    };
+
+    let delimiter = as_byte(delimiter, "delimiter");
+
+    if let Err(e) = delimiter {
+        return Err(e);
+    }
+
+    if options.decimal_separator != '.' && options.decimal_separator != ',' {
+        return Err(SpreadsheetReadingError::InvalidAttributes(format!(
+            "The CSV decimal separator must be '.' or ',', got '{}'",
+            options.decimal_separator
+        )));
+    }
+
+    let quote = as_byte(options.quote, "quote");
+
+    if let Err(e) = quote {
+        return Err(e);
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter.unwrap())
+        .quote(quote.unwrap())
+        .from_reader(text.as_bytes());
+
+    let mut rows = Vec::<Vec<Data>>::new();
+
+    for record in reader.records().skip(options.header_row.saturating_sub(1)) {
+        if let Err(e) = record {
+            return Err(SpreadsheetReadingError::CsvError(e));
+        }
+
+        rows.push(
+            record
+                .unwrap()
+                .iter()
+                .map(|cell| csv_to_data(cell, options.decimal_separator))
+                .collect(),
+        );
+    }
+
+    let width = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+
+    if rows.is_empty() || width == 0 {
+        return Ok(Range::empty());
+    }
+
+    let mut range = Range::new((0, 0), ((rows.len() - 1) as u32, (width - 1) as u32));
+
+    for (y, row) in rows.into_iter().enumerate() {
+        for (x, cell) in row.into_iter().enumerate() {
+            range.set_value((y as u32, x as u32), cell);
+        }
+    }
+
+    return Ok(range);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a file of its own for this test process, so that parallel runs do not share it.
+    fn write_fixture(name: &str, content: &[u8]) -> String {
+        let mut path = std::env::temp_dir();
+        path.push(format!("sti_{}_{}", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        return String::from(path.to_str().unwrap());
+    }
+
+    #[test]
+    fn read_csv_with_options() {
+        // "Tâche;Coût" in Latin-1, preceded by a title line.
+        let path = write_fixture(
+            "latin1.csv",
+            b"Export du mois\nT\xe2che;Co\xfbt\n\"Task; 1\";12,5\nTask 2;3\n",
+        );
+
+        let options = CsvOptions {
+            delimiter: Some(';'),
+            encoding: String::from("latin1"),
+            header_row: 2,
+            decimal_separator: ',',
+            ..CsvOptions::default()
+        };
+
+        let range = read_csv(&path, &options).unwrap();
+
+        assert_eq!(
+            range.get((0, 0)),
+            Some(&Data::String(String::from("Tâche")))
+        );
+        assert_eq!(range.get((0, 1)), Some(&Data::String(String::from("Coût"))));
+        assert_eq!(
+            range.get((1, 0)),
+            Some(&Data::String(String::from("Task; 1")))
+        );
+        assert_eq!(range.get((1, 1)), Some(&Data::Float(12.5)));
+        assert_eq!(range.get((2, 1)), Some(&Data::Int(3)));
+    }
+
+    #[test]
+    fn typed_cells() {
+        assert_eq!(csv_to_data("TRUE", '.'), Data::Bool(true));
+        assert_eq!(
+            csv_to_data("2024-10-01", '.'),
+            Data::DateTimeIso(String::from("2024-10-01"))
+        );
+        assert_eq!(
+            csv_to_data(" PT1H30M ", '.'),
+            Data::DurationIso(String::from("PT1H30M"))
+        );
+        assert_eq!(csv_to_data("-3,25", ','), Data::Float(-3.25));
+        assert_eq!(
+            csv_to_data("Plenty", '.'),
+            Data::String(String::from("Plenty"))
+        );
+        assert_eq!(csv_to_data("-12", '.'), Data::Int(-12));
+        assert_eq!(csv_to_data("0", '.'), Data::Int(0));
+        assert_eq!(csv_to_data("0.5", '.'), Data::Float(0.5));
+        assert_eq!(csv_to_data("1.5e3", '.'), Data::Float(1500.0));
+        assert_eq!(csv_to_data("007", '.'), Data::String(String::from("007")));
+        assert_eq!(csv_to_data("00,5", ','), Data::String(String::from("00,5")));
+
+        assert_eq!(
+            csv_to_data("1,234", '.'),
+            Data::String(String::from("1,234"))
+        );
+        assert_eq!(
+            csv_to_data("1.234", ','),
+            Data::String(String::from("1.234"))
+        );
+        assert_eq!(csv_to_data("1.5", ','), Data::String(String::from("1.5")));
+        assert_eq!(
+            csv_to_data(" Plenty ", '.'),
+            Data::String(String::from("Plenty"))
+        );
+
+        for text in ["NaN", "nan", "inf", "-inf", "infinity", "Infinity", "1e999"] {
+            assert_eq!(csv_to_data(text, '.'), Data::String(String::from(text)));
+        }
+    }
+
+    #[test]
+    fn read_tsv() {
+        let path = write_fixture("tabs.tsv", b"a\tb\n1\t\n");
+
+        let range = read_csv(&path, &CsvOptions::default()).unwrap();
+
+        assert_eq!(range.get((1, 0)), Some(&Data::Int(1)));
+        assert_eq!(range.get((1, 1)), Some(&Data::Empty));
+    }
+
+    #[test]
+    fn unknown_encoding() {
+        let path = write_fixture("encoding.csv", b"a\n1\n");
+
+        let options = CsvOptions {
+            encoding: String::from("klingon"),
+            ..CsvOptions::default()
+        };
+
+        assert!(matches!(
+            read_csv(&path, &options),
+            Err(SpreadsheetReadingError::InvalidAttributes(_))
+        ));
+    }
+}
@@ -1,27 +1,202 @@
+use chrono::NaiveDate;
+use condition::Condition;
+use customer_fields::{ComputedCustomerFields, CustomerTemplate};
+use expression::Expression;
+use filters::Filters;
+use grouping::Grouping;
+use invoice_fields::{ComputedInvoiceFields, InvoiceTemplate, PaymentTerms};
 use regex::Regex;
+use rust_decimal::Decimal;
 use serde::Deserialize;
-use std::{collections::HashMap, fs};
-
-use crate::spreadsheet_parsing::spreadsheet_data::TaskList;
+use std::{collections::HashMap, fs, str::FromStr};
+
+use crate::money;
+use crate::spreadsheet_parsing::{
+    csv_reader::CsvOptions,
+    spreadsheet_data::{ReadingOptions, RowsOptions, SheetSelector, TaskList, Value},
+};
+
+pub mod condition;
+pub mod customer_fields;
+pub mod expression;
+pub mod filters;
+pub mod grouping;
+pub mod invoice_fields;
+pub mod numbering;
 
 #[derive(Debug)]
+#[allow(dead_code, clippy::enum_variant_names)]
 pub enum TemplateMappingError {
     FileOpeningError(std::io::Error),
     ParsingError(toml::de::Error),
     TemplateMappingError(String),
     NumberParsingError(String),
+    /// Invalid expression, `position` being the 0-based character it was found at.
+    ExpressionError {
+        position: usize,
+        message: String,
+    },
+    /// Wraps an error with the output field it happened in.
+    FieldError(String, Box<TemplateMappingError>),
+    /// Wraps an error with the spreadsheet row it happened on.
+    RowError(usize, Box<TemplateMappingError>),
+}
+
+impl TemplateMappingError {
+    fn in_field(self, field: &str) -> Self {
+        return TemplateMappingError::FieldError(String::from(field), Box::new(self));
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct TemplateMapping {
+    #[allow(dead_code)]
     invoice_name: String,
     template_name: String,
-    inputs: HashMap<String, String>,
+    sheet: Option<SheetSelector>,
+    /// Decimals kept on prices and totals, rounded half-up.
+    #[serde(default = "TemplateMapping::default_precision")]
+    precision: u32,
+    /// Decimals kept on quantities, rounded half-up.
+    #[serde(default = "TemplateMapping::default_precision")]
+    quantity_precision: u32,
+    #[serde(default)]
+    csv: CsvOptions,
+    #[serde(default)]
+    rows: RowsOptions,
+    #[serde(default)]
+    filter: RowFilter,
+    group: Option<Grouping>,
+    #[serde(default)]
+    invoice: InvoiceTemplate,
+    /// Input whose values each make an invoice of their own, such as a client column.
+    split_by: Option<String>,
+    /// Customer billed for each value of `split_by`. Values missing here are looked up
+    /// as customer names.
+    #[serde(default)]
+    customers: HashMap<String, CustomerRef>,
+    /// Details of the customers created when missing.
+    #[serde(default)]
+    customer: CustomerTemplate,
+    inputs: HashMap<String, InputBinding>,
     outputs: TemplateMappingOutputs,
+    #[serde(skip)]
+    filters: Filters,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct TemplateMappingInputs(toml::Table);
+/// Column bound to a placeholder in `[inputs]`, either its name alone or
+/// `{ column = "Unité", optional = true, default = "hour" }`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum InputBinding {
+    Column(String),
+    Detailed {
+        column: String,
+        /// Blank or missing cells read as empty text, or 0 in numeric fields.
+        #[serde(default)]
+        optional: bool,
+        /// Used in place of blank cells.
+        default: Option<String>,
+    },
+}
+
+impl InputBinding {
+    pub fn column(&self) -> &str {
+        match self {
+            InputBinding::Column(c) => return c,
+            InputBinding::Detailed { column, .. } => return column,
+        }
+    }
+
+    pub fn optional(&self) -> bool {
+        match self {
+            InputBinding::Column(_) => return false,
+            InputBinding::Detailed { optional, .. } => return *optional,
+        }
+    }
+
+    pub fn default(&self) -> Option<&str> {
+        match self {
+            InputBinding::Column(_) => return None,
+            InputBinding::Detailed { default, .. } => return default.as_deref(),
+        }
+    }
+}
+
+/// Kind of a `${...}` placeholder in an output field.
+enum Tag<'a> {
+    If(&'a str),
+    Elif(&'a str),
+    Else,
+    End,
+    /// `${condition ? then : otherwise}`
+    Choice(&'a str),
+    Input,
+}
+
+impl<'a> Tag<'a> {
+    fn parse(content: &'a str) -> Self {
+        let trimmed = content.trim();
+
+        if let Some(c) = trimmed.strip_prefix("if ") {
+            return Tag::If(c);
+        }
+
+        if let Some(c) = trimmed.strip_prefix("elif ") {
+            return Tag::Elif(c);
+        }
+
+        match trimmed {
+            "else" => return Tag::Else,
+            "end" => return Tag::End,
+            _ => {}
+        }
+
+        // A '?' outside quotes and defaults makes a choice. Filters start at the first
+        // single '|', '||' being the condition operator, and their arguments may hold '?'.
+        let mut quote = None;
+        let bytes = trimmed.as_bytes();
+
+        for (i, c) in trimmed.char_indices() {
+            let in_or = bytes.get(i + 1) == Some(&b'|') || (i > 0 && bytes[i - 1] == b'|');
+
+            match (quote, c) {
+                (None, '\'' | '"') => quote = Some(c),
+                (Some(q), _) if q == c => quote = None,
+                (None, '|') if !in_or => break,
+                (None, '?') if !trimmed[..i].contains(":-") => return Tag::Choice(trimmed),
+                _ => {}
+            }
+        }
+
+        return Tag::Input;
+    }
+}
+
+/// Parts of a placeholder: `name:-default|filter:argument|...`.
+struct Placeholder<'a> {
+    name: &'a str,
+    default: Option<&'a str>,
+    filters: Vec<&'a str>,
+}
+
+impl<'a> Placeholder<'a> {
+    fn parse(placeholder: &'a str) -> Self {
+        let mut parts = placeholder.split('|');
+        let first = parts.next().unwrap_or("");
+
+        let (name, default) = match first.split_once(":-") {
+            Some((n, d)) => (n.trim(), Some(d)),
+            None => (first.trim(), None),
+        };
+
+        return Placeholder {
+            name,
+            default,
+            filters: parts.collect(),
+        };
+    }
+}
 
 #[derive(Debug, Deserialize)]
 pub struct TemplateMappingOutputs {
@@ -32,17 +207,101 @@ pub struct TemplateMappingOutputs {
     sub_total: String,
     total: String,
     unit_name: String,
+    /// Tax types of the item, by name and separated by semicolons, such as `TVA 20%`.
+    taxes: Option<String>,
+}
+
+/// Which rows become invoice items, set under `[filter]` in the template.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RowFilter {
+    /// Condition rows must meet, such as `${status} == 'done'`.
+    #[serde(rename = "where")]
+    pub condition: Option<String>,
+    /// Input holding the date `from` and `to` are checked against.
+    pub date: Option<String>,
+    /// First day kept, included.
+    pub from: Option<NaiveDate>,
+    /// Last day kept, included.
+    pub to: Option<NaiveDate>,
+}
+
+/// Customer given in the template, by id or by name or email.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum CustomerRef {
+    Id(i64),
+    Name(String),
+}
+
+impl CustomerRef {
+    /// Whole numbers are read as ids, which fall back to a name when no customer has them,
+    /// anything else as a name or email.
+    pub fn parse(text: &str) -> Self {
+        if let Ok(id) = text.trim().parse::<i64>() {
+            return CustomerRef::Id(id);
+        }
+
+        return CustomerRef::Name(String::from(text.trim()));
+    }
 }
 
+/// Items of one invoice, along with the `split_by` value they share.
 #[derive(Debug)]
+pub struct InvoiceItems {
+    /// `None` when the template does not split invoices.
+    pub split_key: Option<String>,
+    pub items: Vec<ComputedMappingOutput>,
+    /// Indices of the rows the items come from.
+    pub rows: Vec<usize>,
+}
+
+/// Why a row was left out of the invoice.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SkipReason {
+    NotMatching,
+    BeforeFrom,
+    AfterTo,
+    Undated,
+}
+
+/// How many rows made it to the invoice, and why the others did not.
+#[derive(Debug, Default, PartialEq)]
+pub struct FilterSummary {
+    pub kept: usize,
+    /// Rows not meeting the `where` condition.
+    pub not_matching: usize,
+    pub before_from: usize,
+    pub after_to: usize,
+    /// Rows without a readable date while `from` or `to` is set.
+    pub undated: usize,
+}
+
+impl FilterSummary {
+    pub fn skipped(&self) -> usize {
+        return self.not_matching + self.before_from + self.after_to + self.undated;
+    }
+
+    fn count(&mut self, reason: SkipReason) {
+        match reason {
+            SkipReason::NotMatching => self.not_matching += 1,
+            SkipReason::BeforeFrom => self.before_from += 1,
+            SkipReason::AfterTo => self.after_to += 1,
+            SkipReason::Undated => self.undated += 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ComputedMappingOutput {
     pub name: String,
-    pub quantity: i64,
-    pub price: f64,
+    pub quantity: Decimal,
+    pub price: Decimal,
     pub description: String,
-    pub sub_total: f64,
-    pub total: f64,
+    pub sub_total: Decimal,
+    pub total: Decimal,
     pub unit_name: String,
+    /// Names of the tax types applied to the item.
+    pub taxes: Vec<String>,
 }
 
 impl TemplateMapping {
@@ -64,50 +323,426 @@ impl TemplateMapping {
         ));
     }
 
+    /// Makes a filter usable in placeholders, as in `${task|name}`.
+    #[allow(dead_code)]
+    pub fn register_filter(
+        &mut self,
+        name: &str,
+        filter: impl Fn(Option<&str>, Value) -> Result<Value, TemplateMappingError> + 'static,
+    ) {
+        self.filters.register(name, filter);
+    }
+
+    /// Overrides the `from` and `to` dates of `[filter]`, where given.
+    pub fn set_date_bounds(&mut self, from: Option<NaiveDate>, to: Option<NaiveDate>) {
+        if let Some(_) = from {
+            self.filter.from = from;
+        }
+
+        if let Some(_) = to {
+            self.filter.to = to;
+        }
+    }
+
+    /// Computes the `[invoice]` fields of one invoice. The customer of the `[customers]`
+    /// table comes first, then the one of `[invoice]`, then the one named like the
+    /// `split_by` value.
+    pub fn invoice_fields(
+        &self,
+        list: &TaskList,
+        invoice: &InvoiceItems,
+        variables: &HashMap<String, String>,
+        today: NaiveDate,
+    ) -> Result<ComputedInvoiceFields, TemplateMappingError> {
+        if self.invoice.taxes.is_some() && self.outputs.taxes.is_some() {
+            return Err(TemplateMappingError::TemplateMappingError(String::from(
+                "Taxes apply either to the whole invoice or to every item, not both",
+            )));
+        }
+
+        let fields = self.invoice.compute(self, list, invoice, variables, today);
+
+        if let Err(e) = fields {
+            return Err(e);
+        }
+
+        let mut fields = fields.unwrap();
+
+        if let Some(key) = &invoice.split_key {
+            if self.customers.contains_key(key) || fields.customer.is_none() {
+                fields.customer = Some(self.customer_for(key));
+            }
+        }
+
+        return Ok(fields);
+    }
+
+    /// Computes the `[customer]` fields used to create the customer of one invoice, with
+    /// the same `today` as its `[invoice]` fields.
+    pub fn customer_fields(
+        &self,
+        list: &TaskList,
+        invoice: &InvoiceItems,
+        variables: &HashMap<String, String>,
+        today: NaiveDate,
+    ) -> Result<ComputedCustomerFields, TemplateMappingError> {
+        let data = self.invoice.data(self, list, invoice, variables, today);
+
+        if let Err(e) = data {
+            return Err(e);
+        }
+
+        return self.customer.compute(self, &data.unwrap());
+    }
+
+    /// Overrides the date and payment terms of `[invoice]`, where given.
+    pub fn set_invoice_dates(&mut self, date: Option<NaiveDate>, terms: Option<PaymentTerms>) {
+        if let Some(d) = date {
+            self.invoice.date = Some(d.format("%Y-%m-%d").to_string());
+        }
+
+        if let Some(_) = terms {
+            self.invoice.payment_terms = terms;
+        }
+    }
+
+    /// Overrides the customer of `[invoice]`.
+    pub fn set_customer(&mut self, customer: CustomerRef) {
+        self.invoice.customer = Some(customer);
+    }
+
+    /// Customer to bill for a value of `split_by`.
+    pub fn customer_for(&self, split_key: &str) -> CustomerRef {
+        return self
+            .customers
+            .get(split_key)
+            .cloned()
+            .unwrap_or(CustomerRef::Name(String::from(split_key)));
+    }
+
+    pub fn filter(&self) -> &RowFilter {
+        return &self.filter;
+    }
+
+    /// Whether taxes are set on every item rather than on the whole invoice, when the
+    /// template sets any.
+    pub fn taxes_per_item(&self) -> Option<bool> {
+        match (&self.outputs.taxes, &self.invoice.taxes) {
+            (Some(_), _) => return Some(true),
+            (None, Some(_)) => return Some(false),
+            (None, None) => return None,
+        }
+    }
+
+    fn default_precision() -> u32 {
+        return money::DEFAULT_PRECISION;
+    }
+
+    pub fn template_name(&self) -> &str {
+        return &self.template_name;
+    }
+
+    pub fn reading_options(&self) -> ReadingOptions {
+        let mut rows = self.rows.clone();
+
+        // Blanks in optional inputs do not make a row empty.
+        for binding in self.inputs.values() {
+            let column = String::from(binding.column());
+
+            if binding.optional() && !rows.optional_columns.contains(&column) {
+                rows.optional_columns.push(column);
+            }
+        }
+
+        return ReadingOptions {
+            csv: self.csv.clone(),
+            rows,
+            sheet: self.sheet.clone(),
+        };
+    }
+
+    /// Placeholders, as `${name}`. Braces nested one level deep, as in `${name:-{none}}`,
+    /// belong to the placeholder, so that it ends at the first unmatched `}`.
     fn attr_name_regex() -> Regex {
-        Regex::new(r"\$\{([^\$]*)\}").unwrap()
+        Regex::new(r"\$\{((?:[^\$\{\}]|\{[^\$\{\}]*\})*)\}").unwrap()
     }
 
-    fn apply_line_number<T: std::str::FromStr>(
+    /// Value of the input bound to `name` on the given row.
+    fn placeholder_value(
         &self,
-        line: &str,
+        name: &str,
         data: &TaskList,
         index: usize,
-    ) -> Result<T, TemplateMappingError> {
-        let reg = Self::attr_name_regex();
+    ) -> Result<Value, TemplateMappingError> {
+        // Once computed, aggregates and the values of the invoice context are read like
+        // any other input.
+        for column in [
+            grouping::aggregate_column(name),
+            invoice_fields::context_column(name),
+        ] {
+            if data.headers().contains(&&column) {
+                return Ok(data.get(&column, index).unwrap_or(Value::None));
+            }
+        }
 
-        let mut found = reg.captures_iter(&line);
+        let binding = self.inputs.get(name);
 
-        let word = found.next();
+        if let None = binding {
+            return Err(TemplateMappingError::TemplateMappingError(
+                String::from("No input binding with specified name ") + name,
+            ));
+        }
 
-        if found.next().is_some() {
-            return Err(TemplateMappingError::TemplateMappingError(String::from(
-                "It is not supported to use anything other than a simple value for numeric fields.",
+        let binding = binding.unwrap();
+        let column_name = binding.column();
+
+        if binding.optional() && !data.headers().iter().any(|h| *h == column_name) {
+            return Ok(Value::None);
+        }
+
+        let task_value = data.get(column_name, index);
+
+        if let Err(_) = task_value {
+            return Err(TemplateMappingError::TemplateMappingError(
+                String::from("Could not retrieve value from Excel : ") + column_name,
+            ));
+        }
+
+        return Ok(task_value.unwrap());
+    }
+
+    /// Value of a placeholder such as `time`, `unit:-hour` or `time|hours`: an input
+    /// name, an optional default for blank cells after `:-`, then filters separated by
+    /// `|`, each with an optional argument after a `:`.
+    fn resolve_placeholder(
+        &self,
+        placeholder: &str,
+        data: &TaskList,
+        index: usize,
+    ) -> Result<Value, TemplateMappingError> {
+        let placeholder = Placeholder::parse(placeholder);
+        let mut value = self.placeholder_value(placeholder.name, data, index);
+
+        if let Ok(v) = &value {
+            let default = placeholder
+                .default
+                .or(self.inputs.get(placeholder.name).and_then(|b| b.default()));
+
+            if let (true, Some(d)) = (v.is_empty(), default) {
+                value = Ok(Value::String(String::from(d)));
+            }
+        }
+
+        for filter in placeholder.filters {
+            if let Err(e) = value {
+                return Err(e);
+            }
+
+            let (name, argument) = match filter.split_once(':') {
+                Some((n, a)) => (n, Some(a)),
+                None => (filter, None),
+            };
+
+            value = self.filters.apply(name.trim(), argument, value.unwrap());
+        }
+
+        return value;
+    }
+
+    fn placeholder_number(
+        &self,
+        placeholder: &str,
+        data: &TaskList,
+        index: usize,
+    ) -> Result<Decimal, TemplateMappingError> {
+        let value = self.resolve_placeholder(placeholder, data, index);
+
+        if let Err(e) = value {
+            return Err(e);
+        }
+
+        let value = value.unwrap();
+
+        if value.is_empty() {
+            let name = Placeholder::parse(placeholder).name;
+            let binding = self.inputs.get(name);
+
+            if binding.is_some_and(|b| b.optional()) {
+                return Ok(Decimal::ZERO);
+            }
+
+            return Err(TemplateMappingError::NumberParsingError(format!(
+                "Empty cell in column {}",
+                binding.map(|b| b.column()).unwrap_or(name)
             )));
         }
 
-        let mut res_str = String::from(line);
+        let text = value.to_number_string();
+        let number = Decimal::from_str(text.trim()).or(Decimal::from_scientific(text.trim()));
 
-        if let Some(w) = word {
-            let col_token = &w[1];
-            let col_name = self.inputs.get(col_token);
+        if let Err(_) = number {
+            return Err(TemplateMappingError::NumberParsingError(text));
+        }
 
-            if col_name.is_none() {
-                return Err(TemplateMappingError::TemplateMappingError(
-                    String::from("No input binding with specified name ") + col_token,
-                ));
+        return Ok(number.unwrap());
+    }
+
+    fn has_conditionals(line: &str) -> bool {
+        return Self::attr_name_regex()
+            .captures_iter(line)
+            .any(|c| !matches!(Tag::parse(&c[1]), Tag::Input));
+    }
+
+    fn condition_holds(
+        &self,
+        condition: &str,
+        data: &TaskList,
+        index: usize,
+    ) -> Result<bool, TemplateMappingError> {
+        let condition = Condition::parse(condition);
+
+        if let Err(e) = condition {
+            return Err(e);
+        }
+
+        return condition
+            .unwrap()
+            .holds(&|name| self.resolve_placeholder(name, data, index));
+    }
+
+    /// Resolves `${if ...}`, `${elif ...}`, `${else}` and `${end}` blocks and
+    /// `${condition ? then : otherwise}` choices, leaving other placeholders as is.
+    fn expand_conditionals(
+        &self,
+        line: &str,
+        data: &TaskList,
+        index: usize,
+    ) -> Result<String, TemplateMappingError> {
+        let mut res = String::new();
+        // For every open block: whether its current branch is kept, and whether one was.
+        let mut blocks = Vec::<(bool, bool)>::new();
+        let mut last = 0;
+
+        for capture in Self::attr_name_regex().captures_iter(line) {
+            let placeholder = capture.get(0).unwrap();
+            let outer_kept = |b: &[(bool, bool)]| b.iter().rev().skip(1).all(|(k, _)| *k);
+            let kept = blocks.iter().all(|(k, _)| *k);
+
+            if kept {
+                res.push_str(&line[last..placeholder.start()]);
             }
 
-            res_str = data.get(col_name.unwrap(), index).unwrap().to_string();
+            last = placeholder.end();
+
+            match Tag::parse(&capture[1]) {
+                Tag::If(condition) => {
+                    let holds = if kept {
+                        self.condition_holds(condition, data, index)
+                    } else {
+                        Ok(false)
+                    };
+
+                    if let Err(e) = holds {
+                        return Err(e);
+                    }
+
+                    let holds = holds.unwrap();
+                    blocks.push((holds, holds));
+                }
+                Tag::Elif(condition) => {
+                    if blocks.is_empty() {
+                        return Err(TemplateMappingError::TemplateMappingError(String::from(
+                            "${elif} without ${if}",
+                        )));
+                    }
+
+                    let (_, taken) = *blocks.last().unwrap();
+                    let holds = if !taken && outer_kept(&blocks) {
+                        self.condition_holds(condition, data, index)
+                    } else {
+                        Ok(false)
+                    };
+
+                    if let Err(e) = holds {
+                        return Err(e);
+                    }
+
+                    let holds = holds.unwrap();
+                    *blocks.last_mut().unwrap() = (holds, taken || holds);
+                }
+                Tag::Else => {
+                    if blocks.is_empty() {
+                        return Err(TemplateMappingError::TemplateMappingError(String::from(
+                            "${else} without ${if}",
+                        )));
+                    }
+
+                    let (_, taken) = *blocks.last().unwrap();
+                    *blocks.last_mut().unwrap() = (!taken, true);
+                }
+                Tag::End => {
+                    if let None = blocks.pop() {
+                        return Err(TemplateMappingError::TemplateMappingError(String::from(
+                            "${end} without ${if}",
+                        )));
+                    }
+                }
+                Tag::Choice(choice) if kept => {
+                    let condition = Condition::parse(choice);
+
+                    if let Err(e) = condition {
+                        return Err(e);
+                    }
+
+                    let value = condition
+                        .unwrap()
+                        .eval(&|name| self.resolve_placeholder(name, data, index));
+
+                    if let Err(e) = value {
+                        return Err(e);
+                    }
+
+                    res.push_str(&value.unwrap().to_string());
+                }
+                Tag::Choice(_) => {}
+                Tag::Input if kept => res.push_str(placeholder.as_str()),
+                Tag::Input => {}
+            }
         }
 
-        let as_number = str::parse::<T>(&res_str);
+        if !blocks.is_empty() {
+            return Err(TemplateMappingError::TemplateMappingError(String::from(
+                "${if} without ${end}",
+            )));
+        }
 
-        if let Ok(v) = as_number {
-            return Ok(v);
-        } else {
-            return Err(TemplateMappingError::NumberParsingError(res_str.clone()));
+        res.push_str(&line[last..]);
+
+        return Ok(res);
+    }
+
+    /// Computes a numeric field, which holds an arithmetic expression.
+    fn apply_line_number(
+        &self,
+        line: &str,
+        data: &TaskList,
+        index: usize,
+    ) -> Result<Decimal, TemplateMappingError> {
+        let line = self.expand_conditionals(line, data, index);
+
+        if let Err(e) = line {
+            return Err(e);
+        }
+
+        let expression = Expression::parse(&line.unwrap());
+
+        if let Err(e) = expression {
+            return Err(e);
         }
+
+        return expression
+            .unwrap()
+            .eval(&|name| self.placeholder_number(name, data, index));
     }
 
     fn apply_line_str(
@@ -116,33 +751,29 @@ impl TemplateMapping {
         data: &TaskList,
         index: usize,
     ) -> Result<String, TemplateMappingError> {
+        let line = self.expand_conditionals(line, data, index);
+
+        if let Err(e) = line {
+            return Err(e);
+        }
+
+        let line = line.unwrap();
         let reg = Self::attr_name_regex();
         let found = reg.captures_iter(&line);
-        let mut res: String = String::from(line);
+        let mut res: String = line.clone();
 
         for word in found {
             let w = &word[1];
 
-            let column_name = self.inputs.get(w);
+            let task_value = self.resolve_placeholder(w, data, index);
 
-            if let None = column_name {
-                return Err(TemplateMappingError::TemplateMappingError(
-                    String::from("No input binding with specified name ") + w,
-                ));
-            }
-
-            let str_c = column_name.unwrap();
-            let task_value = data.get(&str_c, index);
-
-            if let Err(_) = task_value {
-                return Err(TemplateMappingError::TemplateMappingError(
-                    String::from("Could not retrieve value from Excel : ") + &str_c,
-                ));
+            if let Err(e) = task_value {
+                return Err(e);
             }
 
             let mut to_replace = String::from("${");
-            to_replace.push_str(&w);
-            to_replace.push_str("}");
+            to_replace.push_str(w);
+            to_replace.push('}');
 
             res = res.replacen(&to_replace, &task_value.unwrap().to_string(), 1);
         }
@@ -150,69 +781,278 @@ impl TemplateMapping {
         return Ok(res);
     }
 
-    pub fn apply(
+    /// Tells whether a row is left out by `[filter]`, and why.
+    fn skip_reason(
         &self,
         list: &TaskList,
-    ) -> Result<Vec<ComputedMappingOutput>, TemplateMappingError> {
-        let mut res = Vec::<ComputedMappingOutput>::new();
-        for index in 0..(list.len()) {
-            let (name, quantity, price, description, sub_total, total, unit_name) = (
-                self.apply_line_str(&self.outputs.name, list, index),
-                self.apply_line_number::<i64>(&self.outputs.quantity, list, index),
-                self.apply_line_number::<f64>(&self.outputs.price, list, index),
-                self.apply_line_str(&self.outputs.description, list, index),
-                self.apply_line_number::<f64>(&self.outputs.sub_total, list, index),
-                self.apply_line_number::<f64>(&self.outputs.total, list, index),
-                self.apply_line_str(&self.outputs.unit_name, list, index),
-            );
+        index: usize,
+    ) -> Result<Option<SkipReason>, TemplateMappingError> {
+        if let (Some(input), true) = (
+            &self.filter.date,
+            self.filter.from.is_some() || self.filter.to.is_some(),
+        ) {
+            let value = self.resolve_placeholder(input, list, index);
+
+            if let Err(e) = value {
+                return Err(e);
+            }
 
-            if name.is_err() {
-                return Err(name.unwrap_err());
+            let date = value.unwrap().as_date().map(|d| d.date());
+
+            match (date, self.filter.from, self.filter.to) {
+                (None, _, _) => return Ok(Some(SkipReason::Undated)),
+                (Some(d), Some(from), _) if d < from => return Ok(Some(SkipReason::BeforeFrom)),
+                (Some(d), _, Some(to)) if d > to => return Ok(Some(SkipReason::AfterTo)),
+                _ => {}
             }
+        }
 
-            if quantity.is_err() {
-                return Err(quantity.unwrap_err());
+        if let Some(condition) = &self.filter.condition {
+            let holds = self.condition_holds(condition, list, index);
+
+            if let Err(e) = holds {
+                return Err(e);
             }
 
-            if price.is_err() {
-                return Err(price.unwrap_err());
+            if !holds.unwrap() {
+                return Ok(Some(SkipReason::NotMatching));
             }
+        }
+
+        return Ok(None);
+    }
 
-            if description.is_err() {
-                return Err(description.unwrap_err());
+    /// Maps the rows kept by `[filter]` into one list of items per value of `split_by`,
+    /// in order of first appearance, telling how many rows were left out.
+    pub fn apply_split(
+        &self,
+        list: &TaskList,
+    ) -> Result<(Vec<InvoiceItems>, FilterSummary), TemplateMappingError> {
+        if let Some(condition) = &self.filter.condition {
+            if let Err(e) = Condition::parse(condition) {
+                return Err(e.in_field("where"));
             }
+        }
 
-            if sub_total.is_err() {
-                return Err(sub_total.unwrap_err());
+        if self.filter.date.is_none() && (self.filter.from.is_some() || self.filter.to.is_some()) {
+            return Err(TemplateMappingError::TemplateMappingError(String::from(
+                "Filtering on dates needs the date input to be set in [filter]",
+            )));
+        }
+
+        let numeric_fields = [
+            ("quantity", &self.outputs.quantity),
+            ("price", &self.outputs.price),
+            ("sub_total", &self.outputs.sub_total),
+            ("total", &self.outputs.total),
+        ];
+
+        // Reports broken expressions once, rather than on the first row.
+        // Fields with conditionals are only known once their row is.
+        for (field, line) in numeric_fields {
+            if Self::has_conditionals(line) {
+                continue;
             }
 
-            if total.is_err() {
-                return Err(total.unwrap_err());
+            if let Err(e) = Expression::parse(line) {
+                return Err(e.in_field(field));
             }
+        }
+
+        let mut kept = Vec::<usize>::new();
+        let mut summary = FilterSummary::default();
 
-            if unit_name.is_err() {
-                return Err(unit_name.unwrap_err());
+        for index in 0..(list.len()) {
+            let reason = self.skip_reason(list, index);
+
+            if let Err(e) = reason {
+                return Err(TemplateMappingError::RowError(
+                    list.row_number(index).unwrap_or(index),
+                    Box::new(e),
+                ));
             }
 
-            res.push(ComputedMappingOutput {
-                name: name.unwrap(),
-                quantity: quantity.unwrap(),
-                price: price.unwrap(),
-                description: description.unwrap(),
-                sub_total: sub_total.unwrap(),
-                total: total.unwrap(),
-                unit_name: unit_name.unwrap(),
-            })
+            match reason.unwrap() {
+                Some(r) => summary.count(r),
+                None => kept.push(index),
+            }
+        }
+
+        summary.kept = kept.len();
+
+        let mut keys = Vec::<Option<String>>::new();
+        let mut parts = HashMap::<Option<String>, Vec<usize>>::new();
+
+        for index in kept {
+            let key = match &self.split_by {
+                Some(input) => self
+                    .resolve_placeholder(input, list, index)
+                    .map(|v| Some(String::from(v.to_string().trim()))),
+                None => Ok(None),
+            };
+
+            if let Err(e) = key {
+                return Err(TemplateMappingError::RowError(
+                    list.row_number(index).unwrap_or(index),
+                    Box::new(e.in_field("split_by")),
+                ));
+            }
+
+            let key = key.unwrap();
+
+            if !parts.contains_key(&key) {
+                keys.push(key.clone());
+            }
+
+            parts.entry(key).or_default().push(index);
+        }
+
+        let mut res = Vec::<InvoiceItems>::new();
+
+        for key in keys {
+            let items = self.apply_rows(list, &parts[&key]);
+
+            if let Err(e) = items {
+                return Err(e);
+            }
+
+            res.push(InvoiceItems {
+                items: items.unwrap(),
+                rows: parts.remove(&key).unwrap_or_default(),
+                split_key: key,
+            });
+        }
+
+        return Ok((res, summary));
+    }
+
+    /// Maps the rows at `indices`, grouping them first when the template says so.
+    fn apply_rows(
+        &self,
+        list: &TaskList,
+        indices: &[usize],
+    ) -> Result<Vec<ComputedMappingOutput>, TemplateMappingError> {
+        let grouped = self.group.as_ref().map(|g| g.group(self, list, indices));
+
+        if let Some(Err(e)) = grouped {
+            return Err(e);
         }
+
+        // Grouped rows are all kept, one item per group.
+        let (list, indices) = match &grouped {
+            Some(Ok(g)) => (g, (0..g.len()).collect()),
+            _ => (list, indices.to_vec()),
+        };
+
+        let mut res = Vec::<ComputedMappingOutput>::new();
+
+        for index in indices {
+            let item = self.apply_row(list, index);
+
+            if let Err(e) = item {
+                return Err(TemplateMappingError::RowError(
+                    list.row_number(index).unwrap_or(index),
+                    Box::new(e),
+                ));
+            }
+
+            res.push(item.unwrap());
+        }
+
         return Ok(res);
     }
+
+    fn apply_row(
+        &self,
+        list: &TaskList,
+        index: usize,
+    ) -> Result<ComputedMappingOutput, TemplateMappingError> {
+        let (name, quantity, price, description, sub_total, total, unit_name) = (
+            self.apply_line_str(&self.outputs.name, list, index)
+                .map_err(|e| e.in_field("name")),
+            self.apply_line_number(&self.outputs.quantity, list, index)
+                .map_err(|e| e.in_field("quantity")),
+            self.apply_line_number(&self.outputs.price, list, index)
+                .map_err(|e| e.in_field("price")),
+            self.apply_line_str(&self.outputs.description, list, index)
+                .map_err(|e| e.in_field("description")),
+            self.apply_line_number(&self.outputs.sub_total, list, index)
+                .map_err(|e| e.in_field("sub_total")),
+            self.apply_line_number(&self.outputs.total, list, index)
+                .map_err(|e| e.in_field("total")),
+            self.apply_line_str(&self.outputs.unit_name, list, index)
+                .map_err(|e| e.in_field("unit_name")),
+        );
+
+        if name.is_err() {
+            return Err(name.unwrap_err());
+        }
+
+        if quantity.is_err() {
+            return Err(quantity.unwrap_err());
+        }
+
+        if price.is_err() {
+            return Err(price.unwrap_err());
+        }
+
+        if description.is_err() {
+            return Err(description.unwrap_err());
+        }
+
+        if sub_total.is_err() {
+            return Err(sub_total.unwrap_err());
+        }
+
+        if total.is_err() {
+            return Err(total.unwrap_err());
+        }
+
+        if unit_name.is_err() {
+            return Err(unit_name.unwrap_err());
+        }
+
+        let taxes = match &self.outputs.taxes {
+            Some(t) => self
+                .apply_line_str(t, list, index)
+                .map(|t| split_names(&t))
+                .map_err(|e| e.in_field("taxes")),
+            None => Ok(Vec::new()),
+        };
+
+        if let Err(e) = taxes {
+            return Err(e);
+        }
+
+        return Ok(ComputedMappingOutput {
+            name: name.unwrap(),
+            quantity: money::round(quantity.unwrap(), self.quantity_precision),
+            price: money::round(price.unwrap(), self.precision),
+            description: description.unwrap(),
+            sub_total: money::round(sub_total.unwrap(), self.precision),
+            total: money::round(total.unwrap(), self.precision),
+            unit_name: unit_name.unwrap(),
+            taxes: taxes.unwrap(),
+        });
+    }
+}
+
+/// Non-empty names of a list separated by semicolons, commas being part of rates such as
+/// `TVA 5,5%`.
+fn split_names(text: &str) -> Vec<String> {
+    return text
+        .split(';')
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .map(String::from)
+        .collect();
 }
 
 #[cfg(test)]
 mod tests {
-    use std::f64;
+    use rust_decimal_macros::dec;
 
-    use crate::spreadsheet_parsing::spreadsheet_data;
+    use crate::spreadsheet_parsing::spreadsheet_data::{self, TaskRow};
 
     use super::*;
 
@@ -224,9 +1064,9 @@ mod tests {
         assert_eq!(mapping.invoice_name, "ma_facture");
         assert_eq!(mapping.template_name, "Une facture pour ");
 
-        assert_eq!(mapping.inputs.get("task").unwrap(), "Tâche");
-        assert_eq!(mapping.inputs.get("time").unwrap(), "Durée");
-        assert_eq!(mapping.inputs.get("cost").unwrap(), "Coût");
+        assert_eq!(mapping.inputs.get("task").unwrap().column(), "Tâche");
+        assert_eq!(mapping.inputs.get("time").unwrap().column(), "Durée");
+        assert_eq!(mapping.inputs.get("cost").unwrap().column(), "Coût");
 
         assert_eq!(mapping.outputs.name, "${task} : ${time}");
         assert_eq!(mapping.outputs.quantity, "1");
@@ -237,6 +1077,20 @@ mod tests {
         assert_eq!(mapping.outputs.unit_name, "Tâche");
     }
 
+    #[test]
+    fn tag_kinds() {
+        assert!(matches!(Tag::parse("ti > 3 ? 'a' : 'b'"), Tag::Choice(_)));
+        assert!(matches!(
+            Tag::parse("ti > 3 || tf < 1 ? 'a' : 'b'"),
+            Tag::Choice(_)
+        ));
+        assert!(matches!(Tag::parse("ti > 3 ? 'a|b' : 'c'"), Tag::Choice(_)));
+        assert!(matches!(Tag::parse("tc|date:%d?%m"), Tag::Input));
+        assert!(matches!(Tag::parse("te|truncate:5|upper"), Tag::Input));
+        assert!(matches!(Tag::parse("te:-why?"), Tag::Input));
+        assert!(matches!(Tag::parse("if ti > 3"), Tag::If("ti > 3")));
+    }
+
     #[test]
     fn template_regex() {
         let str_1 = "test test test";
@@ -267,67 +1121,117 @@ mod tests {
         assert_eq!(cap_5.len(), 2);
         assert_eq!(&cap_5[0], "${test}");
         assert_eq!(&cap_5[1], "test");
+
+        let str_6 = "INV-${test}-{MM}-{seq:4}";
+        let cap_6 = TemplateMapping::attr_name_regex().captures(str_6).unwrap();
+        assert_eq!(&cap_6[0], "${test}");
+        assert_eq!(&cap_6[1], "test");
+
+        let str_7 = "${test:-{none}} and {seq}";
+        let cap_7 = TemplateMapping::attr_name_regex().captures(str_7).unwrap();
+        assert_eq!(&cap_7[0], "${test:-{none}}");
+        assert_eq!(&cap_7[1], "test:-{none}");
+
+        let str_8 = "${test|date:{%Y}}-{seq}";
+        let cap_8 = TemplateMapping::attr_name_regex().captures(str_8).unwrap();
+        assert_eq!(&cap_8[1], "test|date:{%Y}");
+
+        let cap_9: Vec<String> = TemplateMapping::attr_name_regex()
+            .captures_iter("${a}{b}${c}")
+            .map(|c| String::from(&c[1]))
+            .collect();
+        assert_eq!(cap_9, vec!["a", "c"]);
     }
 
-    fn get_fake_task_list() -> TaskList {
-        let mut task_hashmap = HashMap::<String, Vec<spreadsheet_data::Value>>::new();
+    /// Items of every invoice in one list, telling how many rows were left out.
+    fn mapped_items_with_summary(
+        mapping: &TemplateMapping,
+        list: &TaskList,
+    ) -> Result<(Vec<ComputedMappingOutput>, FilterSummary), TemplateMappingError> {
+        return mapping.apply_split(list).map(|(invoices, summary)| {
+            (
+                invoices.into_iter().flat_map(|i| i.items).collect(),
+                summary,
+            )
+        });
+    }
 
-        task_hashmap.insert(
-            String::from("Test composé"),
-            vec![
-                spreadsheet_data::Value::String(String::from("some")),
-                spreadsheet_data::Value::String(String::from("things")),
-                spreadsheet_data::Value::String(String::from("in")),
-                spreadsheet_data::Value::String(String::from("an")),
-                spreadsheet_data::Value::String(String::from("array")),
-            ],
-        );
+    fn mapped_items(
+        mapping: &TemplateMapping,
+        list: &TaskList,
+    ) -> Result<Vec<ComputedMappingOutput>, TemplateMappingError> {
+        return mapped_items_with_summary(mapping, list).map(|(items, _)| items);
+    }
 
-        task_hashmap.insert(
-            String::from("Test Encore"),
-            vec![
-                spreadsheet_data::Value::String(String::from("yet")),
-                spreadsheet_data::Value::String(String::from("other")),
-                spreadsheet_data::Value::String(String::from("things")),
-                spreadsheet_data::Value::String(String::from("in")),
-                spreadsheet_data::Value::String(String::from("there")),
-            ],
-        );
+    fn today() -> NaiveDate {
+        return NaiveDate::from_ymd_opt(2024, 11, 4).unwrap();
+    }
 
-        task_hashmap.insert(
+    fn get_fake_task_list() -> TaskList {
+        let headers = vec![
+            String::from("Test composé"),
+            String::from("Test Encore"),
             String::from("Test Floats"),
-            vec![
-                spreadsheet_data::Value::Float(1.1),
-                spreadsheet_data::Value::Float(2.0),
-                spreadsheet_data::Value::Float(3.3),
-                spreadsheet_data::Value::Float(9.2),
-                spreadsheet_data::Value::Float(10.0),
-            ],
-        );
-
-        task_hashmap.insert(
             String::from("Test Ints"),
-            vec![
-                spreadsheet_data::Value::Integer(1),
-                spreadsheet_data::Value::Integer(2),
-                spreadsheet_data::Value::Integer(3),
-                spreadsheet_data::Value::Integer(9),
-                spreadsheet_data::Value::Integer(10),
-            ],
-        );
-        return TaskList::from(task_hashmap);
+            String::from("Test Durations"),
+        ];
+
+        let rows = [
+            ("some", "yet", 1.1, 1),
+            ("things", "other", 2.0, 2),
+            ("in", "things", 3.3, 3),
+            ("an", "in", 9.2, 9),
+            ("array", "there", 10.0, 10),
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, (tc, te, tf, ti))| {
+            TaskRow::new(
+                i + 2,
+                vec![
+                    spreadsheet_data::Value::String(String::from(*tc)),
+                    spreadsheet_data::Value::String(String::from(*te)),
+                    spreadsheet_data::Value::Float(*tf),
+                    spreadsheet_data::Value::Integer(*ti),
+                    spreadsheet_data::Value::Duration(chrono::Duration::minutes(15 * *ti)),
+                ],
+            )
+        })
+        .collect();
+
+        return TaskList::from(headers, rows);
     }
 
     fn get_fake_mapping() -> TemplateMapping {
         let mut inputs = HashMap::new();
-        inputs.insert(String::from("tc"), String::from("Test composé"));
-        inputs.insert(String::from("te"), String::from("Test Encore"));
-        inputs.insert(String::from("tf"), String::from("Test Floats"));
-        inputs.insert(String::from("ti"), String::from("Test Ints"));
+        for (name, column) in [
+            ("tc", "Test composé"),
+            ("te", "Test Encore"),
+            ("tf", "Test Floats"),
+            ("ti", "Test Ints"),
+            ("du", "Test Durations"),
+        ] {
+            inputs.insert(
+                String::from(name),
+                InputBinding::Column(String::from(column)),
+            );
+        }
         TemplateMapping {
             template_name: String::from("test template"),
             invoice_name: String::from("test invoice"),
+            sheet: None,
+            precision: money::DEFAULT_PRECISION,
+            quantity_precision: money::DEFAULT_PRECISION,
+            csv: CsvOptions::default(),
+            rows: RowsOptions::default(),
+            filter: RowFilter::default(),
+            group: None,
+            invoice: InvoiceTemplate::default(),
+            split_by: None,
+            customers: HashMap::new(),
+            customer: CustomerTemplate::default(),
             inputs,
+            filters: Filters::default(),
             outputs: TemplateMappingOutputs {
                 name: String::from("$nom ${tc}"),
                 quantity: String::from("${ti}"),
@@ -336,6 +1240,7 @@ mod tests {
                 sub_total: String::from("${tf}"),
                 total: String::from("${tf}"),
                 unit_name: String::from("${te}${tc}"),
+                taxes: None,
             },
         }
     }
@@ -369,6 +1274,11 @@ mod tests {
             .unwrap();
         assert_eq!(line_6, "yo in yo in yo in");
 
+        let line_9 = mapping
+            .apply_line_str("INV-${te}-{MM}-{seq:4}", &task_list, 3)
+            .unwrap();
+        assert_eq!(line_9, "INV-in-{MM}-{seq:4}");
+
         let line_7 = mapping.apply_line_str("yo", &task_list, 999).unwrap();
         assert_eq!(line_7, "yo");
 
@@ -381,29 +1291,711 @@ mod tests {
         let mapping = get_fake_mapping();
         let task_list = get_fake_task_list();
 
+        let line_1 = mapping.apply_line_number("${tf} a", &task_list, 3);
+        assert!(matches!(
+            line_1,
+            Err(TemplateMappingError::ExpressionError { position: 6, .. })
+        ));
+
+        let line_2 = mapping.apply_line_number("${tf} ${ti}", &task_list, 3);
+        assert!(line_2.is_err());
+
+        let line_3 = mapping.apply_line_number("${tf}", &task_list, 2).unwrap();
+        assert_eq!(line_3, dec!(3.3));
+
+        let line_4 = mapping.apply_line_number("${ti}", &task_list, 3).unwrap();
+        assert_eq!(line_4, dec!(9));
+
+        let line_5 = mapping.apply_line_number("yo", &task_list, 3);
+        assert!(line_5.is_err());
+
+        let line_6 = mapping.apply_line_number("", &task_list, 3);
+        assert!(line_6.is_err());
+
+        let line_7 = mapping.apply_line_number("${du}", &task_list, 1).unwrap();
+        assert_eq!(line_7, dec!(0.5));
+
+        let line_8 = mapping
+            .apply_line_str("${tc}: ${du}", &task_list, 3)
+            .unwrap();
+        assert_eq!(line_8, "an: 2:15");
+    }
+
+    #[test]
+    fn apply_duration_filters() {
+        let mapping = get_fake_mapping();
+        let task_list = get_fake_task_list();
+
         let line_1 = mapping
-            .apply_line_number::<f64>("${tf} a", &task_list, 3)
+            .apply_line_number("${du|hours} * ${tf}", &task_list, 3)
             .unwrap();
-        assert_eq!(line_1, 9.2);
+        assert_eq!(line_1, dec!(20.7));
 
-        let line_2 = mapping.apply_line_number::<f64>("${tf} ${ti}", &task_list, 3);
-        assert!(line_2.is_err());
+        let line_2 = mapping
+            .apply_line_str("${du|minutes} min", &task_list, 3)
+            .unwrap();
+        assert_eq!(line_2, "135 min");
+
+        let line_3 = mapping.apply_line_number("${tc|hours}", &task_list, 3);
+        assert!(matches!(
+            line_3,
+            Err(TemplateMappingError::NumberParsingError(_))
+        ));
+    }
+
+    #[test]
+    fn apply_formatting_filters() {
+        let mut mapping = get_fake_mapping();
+        mapping.register_filter("initial", |_, v| {
+            return Ok(Value::String(v.to_string().chars().take(1).collect()));
+        });
+        let task_list = get_fake_task_list();
+
+        let line_1 = mapping
+            .apply_line_str(
+                "${tc|upper} ${tf|number:2,fr} ${te|truncate:3} ${te|initial}",
+                &task_list,
+                4,
+            )
+            .unwrap();
+        assert_eq!(line_1, "ARRAY 10,00 th… t");
+
+        let line_2 = mapping
+            .apply_line_str("${du|hours|number:1,fr} h", &task_list, 3)
+            .unwrap();
+        assert_eq!(line_2, "2,3 h");
+
+        let line_3 = mapping.apply_line_str("${tc|shout}", &task_list, 0);
+        assert!(matches!(
+            line_3,
+            Err(TemplateMappingError::TemplateMappingError(_))
+        ));
+    }
+
+    #[test]
+    fn apply_conditionals() {
+        let mapping = get_fake_mapping();
+        let task_list = get_fake_task_list();
+        let unit = "${if te == 'in'}km${elif ti > 2}${te}${else}h${end}";
+
+        assert_eq!(mapping.apply_line_str(unit, &task_list, 3).unwrap(), "km");
+        assert_eq!(
+            mapping.apply_line_str(unit, &task_list, 4).unwrap(),
+            "there"
+        );
+        assert_eq!(mapping.apply_line_str(unit, &task_list, 0).unwrap(), "h");
+
+        let nested = "a${if ti > 1}b${if tc == 'in'}c${end}d${end}e";
+        assert_eq!(mapping.apply_line_str(nested, &task_list, 0).unwrap(), "ae");
+        assert_eq!(
+            mapping.apply_line_str(nested, &task_list, 1).unwrap(),
+            "abde"
+        );
+        assert_eq!(
+            mapping.apply_line_str(nested, &task_list, 2).unwrap(),
+            "abcde"
+        );
+
+        let line_1 = mapping
+            .apply_line_str("${tf >= 9.2 ? 'big' : tc}", &task_list, 3)
+            .unwrap();
+        assert_eq!(line_1, "big");
+
+        let line_2 = mapping
+            .apply_line_str("${tf >= 9.2 ? 'big' : tc}", &task_list, 0)
+            .unwrap();
+        assert_eq!(line_2, "some");
 
         let line_3 = mapping
-            .apply_line_number::<f64>("${tf}", &task_list, 2)
+            .apply_line_number(
+                "${if te == 'other'}0${else}${tf} * ${ti}${end}",
+                &task_list,
+                1,
+            )
             .unwrap();
-        assert_eq!(line_3, 3.3);
+        assert_eq!(line_3, dec!(0));
 
         let line_4 = mapping
-            .apply_line_number::<i64>("${ti}", &task_list, 3)
+            .apply_line_number("${ti > 5 ? 2 : 1} * ${tf}", &task_list, 3)
             .unwrap();
-        assert_eq!(line_4, 9);
+        assert_eq!(line_4, dec!(18.4));
+
+        assert!(mapping
+            .apply_line_str("${if ti > 1}a", &task_list, 0)
+            .is_err());
+        assert!(mapping.apply_line_str("a${end}", &task_list, 0).is_err());
+        assert!(matches!(
+            mapping.apply_line_str("${if ti = 1}a${end}", &task_list, 0),
+            Err(TemplateMappingError::ExpressionError { position: 3, .. })
+        ));
+    }
 
-        let line_5 = mapping.apply_line_number::<f64>("yo", &task_list, 3);
-        assert!(line_5.is_err());
+    #[test]
+    fn apply_row_filter() {
+        let mut mapping = get_fake_mapping();
+        mapping.inputs.insert(
+            String::from("day"),
+            InputBinding::Column(String::from("Test composé")),
+        );
+        mapping.filter = RowFilter {
+            condition: Some(String::from("${ti} != 9 && te != 'in'")),
+            date: Some(String::from("day")),
+            from: NaiveDate::from_ymd_opt(2024, 10, 2),
+            to: None,
+        };
+        mapping.set_date_bounds(None, NaiveDate::from_ymd_opt(2024, 10, 30));
+
+        let task_list = TaskList::from(
+            vec![
+                String::from("Test composé"),
+                String::from("Test Encore"),
+                String::from("Test Floats"),
+                String::from("Test Ints"),
+            ],
+            [
+                ("2024-10-01", "yet", 1),
+                ("2024-10-02", "other", 2),
+                ("2024-10-15", "in", 3),
+                ("2024-10-30", "there", 9),
+                ("2024-10-30", "there", 4),
+                ("2024-11-01", "there", 5),
+                ("someday", "there", 6),
+            ]
+            .iter()
+            .enumerate()
+            .map(|(i, (day, te, ti))| {
+                TaskRow::new(
+                    i + 2,
+                    vec![
+                        Value::String(String::from(*day)),
+                        Value::String(String::from(*te)),
+                        Value::Float(1.5),
+                        Value::Integer(*ti),
+                    ],
+                )
+            })
+            .collect(),
+        );
 
-        let line_6 = mapping.apply_line_number::<f64>("", &task_list, 3);
-        assert!(line_6.is_err());
+        let (items, summary) = mapped_items_with_summary(&mapping, &task_list).unwrap();
+
+        assert_eq!(
+            items.iter().map(|i| i.quantity).collect::<Vec<_>>(),
+            vec![dec!(2), dec!(4)]
+        );
+        assert_eq!(
+            summary,
+            FilterSummary {
+                kept: 2,
+                not_matching: 2,
+                before_from: 1,
+                after_to: 1,
+                undated: 1,
+            }
+        );
+        assert_eq!(summary.skipped(), 5);
+
+        mapping.filter.date = None;
+        assert!(mapped_items(&mapping, &task_list).is_err());
+
+        mapping.filter = RowFilter {
+            condition: Some(String::from("ti >")),
+            ..RowFilter::default()
+        };
+        assert!(matches!(
+            mapped_items(&mapping, &task_list),
+            Err(TemplateMappingError::FieldError(field, _)) if field == "where"
+        ));
+    }
+
+    #[test]
+    fn apply_grouped() {
+        let mut mapping = get_fake_mapping();
+        mapping.group = Some(Grouping {
+            by: String::from("${ti > 2 ? 'big' : 'small'}"),
+            aggregates: HashMap::from(
+                [
+                    ("hours", "sum(${du|hours})"),
+                    ("rows", "count()"),
+                    ("smallest", "min(${tf})"),
+                    ("largest", "max(${tf})"),
+                    ("first", "first(${tc})"),
+                    ("words", "join(${te|upper}, '/')"),
+                ]
+                .map(|(n, a)| (String::from(n), String::from(a))),
+            ),
+        });
+        mapping.outputs.name = String::from("${tc}: ${words} (${rows})");
+        mapping.outputs.description = String::from("${first} ${smallest}-${largest}");
+        mapping.outputs.quantity = String::from("${hours}");
+        mapping.outputs.price = String::from("${largest} * 2");
+        let task_list = get_fake_task_list();
+
+        let (items, summary) = mapped_items_with_summary(&mapping, &task_list).unwrap();
+
+        assert_eq!(summary.kept, 5);
+        assert_eq!(items.len(), 2);
+
+        assert_eq!(items[0].name, "some: YET/OTHER (2)");
+        assert_eq!(items[0].description, "some 1.1-2");
+        assert_eq!(items[0].quantity, dec!(0.75));
+        assert_eq!(items[0].price, dec!(4));
+
+        assert_eq!(items[1].name, "in: THINGS/IN/THERE (3)");
+        assert_eq!(items[1].description, "in 3.3-10");
+        assert_eq!(items[1].quantity, dec!(5.5));
+        assert_eq!(items[1].price, dec!(20));
+
+        mapping.group.as_mut().unwrap().aggregates =
+            HashMap::from([(String::from("bad"), String::from("sum(${tc})"))]);
+
+        let items = mapped_items(&mapping, &task_list);
+        assert!(matches!(
+            items,
+            Err(TemplateMappingError::RowError(2, e))
+                if matches!(*e, TemplateMappingError::FieldError(ref f, _) if f == "bad")
+        ));
+    }
+
+    #[test]
+    fn apply_split() {
+        let mut mapping = get_fake_mapping();
+        mapping.split_by = Some(String::from("te"));
+        mapping
+            .customers
+            .insert(String::from("in"), CustomerRef::Id(4));
+        let task_list = TaskList::from(
+            vec![String::from("Test Encore"), String::from("Test Ints")],
+            ["in", "there", "in", " there "]
+                .iter()
+                .enumerate()
+                .map(|(i, te)| {
+                    TaskRow::new(
+                        i + 2,
+                        vec![Value::String(String::from(*te)), Value::Integer(i as i64)],
+                    )
+                })
+                .collect(),
+        );
+        mapping.outputs = TemplateMappingOutputs {
+            name: String::from("${te}"),
+            quantity: String::from("${ti}"),
+            price: String::from("1"),
+            description: String::new(),
+            sub_total: String::from("1"),
+            total: String::from("1"),
+            unit_name: String::new(),
+            taxes: None,
+        };
+
+        let (invoices, summary) = mapping.apply_split(&task_list).unwrap();
+
+        assert_eq!(summary.kept, 4);
+        assert_eq!(invoices.len(), 2);
+        assert_eq!(invoices[0].split_key.as_deref(), Some("in"));
+        assert_eq!(
+            invoices[0]
+                .items
+                .iter()
+                .map(|i| i.quantity)
+                .collect::<Vec<_>>(),
+            vec![dec!(0), dec!(2)]
+        );
+        assert_eq!(invoices[1].split_key.as_deref(), Some("there"));
+        assert_eq!(invoices[1].items.len(), 2);
+
+        assert_eq!(mapping.customer_for("in"), CustomerRef::Id(4));
+        assert_eq!(
+            mapping.customer_for("there"),
+            CustomerRef::Name(String::from("there"))
+        );
+
+        mapping.split_by = None;
+        let (invoices, _) = mapping.apply_split(&task_list).unwrap();
+        assert_eq!(invoices.len(), 1);
+        assert_eq!(invoices[0].split_key, None);
+        assert_eq!(invoices[0].items.len(), 4);
+    }
+
+    #[test]
+    fn apply_invoice_fields() {
+        let mut mapping = get_fake_mapping();
+        mapping.invoice = toml::from_str(
+            r#"
+            customer = "${te}"
+            number = "INV-${month}-${item_count}"
+            date = "2024-10-${last}"
+            due_days = 15
+            currency = "EUR"
+            notes = "${hours} hours, ${total} in total for ${tc}"
+            discount = "${ti}%"
+            template = "invoice1"
+
+            [aggregates]
+            hours = "sum(${du|hours})"
+            last = "max(${ti} * 3)"
+            "#,
+        )
+        .unwrap();
+        let task_list = get_fake_task_list();
+        let variables = HashMap::from([(String::from("month"), String::from("10"))]);
+
+        let (invoices, _) = mapping.apply_split(&task_list).unwrap();
+        let fields = mapping
+            .invoice_fields(&task_list, &invoices[0], &variables, today())
+            .unwrap();
+
+        assert_eq!(
+            fields.customer,
+            Some(CustomerRef::Name(String::from("yet")))
+        );
+        assert_eq!(fields.number.as_deref(), Some("INV-10-5"));
+        assert_eq!(fields.date, NaiveDate::from_ymd_opt(2024, 10, 30).unwrap());
+        assert_eq!(
+            fields.due_date,
+            NaiveDate::from_ymd_opt(2024, 11, 14).unwrap()
+        );
+        assert_eq!(fields.currency.as_deref(), Some("EUR"));
+        assert_eq!(
+            fields.notes.as_deref(),
+            Some("6.25 hours, 25.6 in total for some")
+        );
+        assert_eq!(
+            fields.discount,
+            Some(invoice_fields::Discount::Percentage(dec!(1)))
+        );
+        assert_eq!(fields.template_name, "invoice1");
+
+        mapping.invoice = toml::from_str("customer = 12").unwrap();
+        mapping.split_by = Some(String::from("te"));
+        mapping
+            .customers
+            .insert(String::from("in"), CustomerRef::Id(4));
+
+        let (invoices, _) = mapping.apply_split(&task_list).unwrap();
+        let customers: Vec<_> = invoices
+            .iter()
+            .map(|i| {
+                mapping
+                    .invoice_fields(&task_list, i, &variables, today())
+                    .unwrap()
+                    .customer
+            })
+            .collect();
+
+        assert_eq!(customers[0], Some(CustomerRef::Id(12)));
+        assert_eq!(customers[3], Some(CustomerRef::Id(4)));
+
+        let fields = mapping
+            .invoice_fields(&task_list, &invoices[0], &variables, today())
+            .unwrap();
+        assert_eq!(fields.number, None);
+        assert_eq!(fields.due_date, fields.date + chrono::Duration::days(30));
+        assert_eq!(fields.template_name, "test template");
+
+        mapping.set_customer(CustomerRef::parse(" billing@acme.test "));
+        let fields = mapping
+            .invoice_fields(&task_list, &invoices[0], &variables, today())
+            .unwrap();
+        assert_eq!(
+            fields.customer,
+            Some(CustomerRef::Name(String::from("billing@acme.test")))
+        );
+    }
+
+    #[test]
+    fn apply_name_collisions() {
+        let mut mapping = get_fake_mapping();
+        let task_list = get_fake_task_list();
+        let (invoices, _) = mapping.apply_split(&task_list).unwrap();
+        let variables = HashMap::from([(String::from("te"), String::from("variable"))]);
+
+        mapping.invoice = toml::from_str("notes = \"${te}\"").unwrap();
+        assert!(mapping
+            .invoice_fields(&task_list, &invoices[0], &variables, today())
+            .is_err());
+
+        mapping.invoice = toml::from_str(
+            r#"
+            notes = "${tc}"
+            [aggregates]
+            tc = "count()"
+            "#,
+        )
+        .unwrap();
+        assert!(mapping
+            .invoice_fields(&task_list, &invoices[0], &HashMap::new(), today())
+            .is_err());
+
+        // Inputs hide the built-in values of the same name.
+        mapping.inputs.insert(
+            String::from("total"),
+            InputBinding::Column(String::from("Test composé")),
+        );
+        mapping.invoice = toml::from_str("notes = \"${total} for ${item_count}\"").unwrap();
+        let fields = mapping
+            .invoice_fields(&task_list, &invoices[0], &HashMap::new(), today())
+            .unwrap();
+        assert_eq!(fields.notes.as_deref(), Some("some for 5"));
+
+        mapping.group = Some(
+            toml::from_str(
+                r#"
+                by = "${te}"
+                [aggregates]
+                ti = "sum(${ti})"
+                "#,
+            )
+            .unwrap(),
+        );
+        assert!(mapped_items(&mapping, &task_list).is_err());
+    }
+
+    #[test]
+    fn apply_invoice_dates() {
+        let mut mapping = get_fake_mapping();
+        mapping.invoice = toml::from_str(
+            r#"
+            latest_date = "tc"
+            terms = "end of month + ${ti}"
+            "#,
+        )
+        .unwrap();
+        let task_list = TaskList::from(
+            vec![String::from("Test composé"), String::from("Test Ints")],
+            ["2024-10-03", "", "2024-10-21", "2024-10-09"]
+                .iter()
+                .enumerate()
+                .map(|(i, tc)| {
+                    TaskRow::new(
+                        i + 2,
+                        vec![Value::String(String::from(*tc)), Value::Integer(15)],
+                    )
+                })
+                .collect(),
+        );
+        let invoice = InvoiceItems {
+            split_key: None,
+            items: Vec::new(),
+            rows: vec![0, 1, 2, 3],
+        };
+        let variables = HashMap::new();
+
+        let fields = mapping
+            .invoice_fields(&task_list, &invoice, &variables, today())
+            .unwrap();
+        assert_eq!(fields.date, NaiveDate::from_ymd_opt(2024, 10, 21).unwrap());
+        assert_eq!(
+            fields.due_date,
+            NaiveDate::from_ymd_opt(2024, 11, 15).unwrap()
+        );
+
+        mapping.set_invoice_dates(
+            NaiveDate::from_ymd_opt(2024, 12, 1),
+            Some(PaymentTerms::Net(10)),
+        );
+        let fields = mapping
+            .invoice_fields(&task_list, &invoice, &variables, today())
+            .unwrap();
+        assert_eq!(fields.date, NaiveDate::from_ymd_opt(2024, 12, 1).unwrap());
+        assert_eq!(
+            fields.due_date,
+            NaiveDate::from_ymd_opt(2024, 12, 11).unwrap()
+        );
+
+        mapping.set_invoice_dates(
+            None,
+            Some(PaymentTerms::Date(
+                NaiveDate::from_ymd_opt(2024, 11, 30).unwrap(),
+            )),
+        );
+        assert!(matches!(
+            mapping.invoice_fields(&task_list, &invoice, &variables, today()),
+            Err(TemplateMappingError::FieldError(f, _)) if f == "terms"
+        ));
+
+        mapping.invoice = toml::from_str("latest_date = \"tc\"").unwrap();
+        let undated = InvoiceItems {
+            split_key: None,
+            items: Vec::new(),
+            rows: vec![1],
+        };
+        assert!(matches!(
+            mapping.invoice_fields(&task_list, &undated, &variables, today()),
+            Err(TemplateMappingError::FieldError(f, _)) if f == "latest_date"
+        ));
+
+        mapping.invoice = toml::from_str("terms = \"whenever\"").unwrap();
+        assert!(matches!(
+            mapping.invoice_fields(&task_list, &invoice, &variables, today()),
+            Err(TemplateMappingError::FieldError(f, _)) if f == "terms"
+        ));
+    }
+
+    #[test]
+    fn apply_taxes() {
+        let mut mapping = get_fake_mapping();
+        mapping.outputs.taxes = Some(String::from("TVA ${ti}%; ${if ti > 3}Local${end};"));
+        let task_list = get_fake_task_list();
+
+        let items = mapped_items(&mapping, &task_list).unwrap();
+        assert_eq!(items[0].taxes, vec![String::from("TVA 1%")]);
+        assert_eq!(
+            items[3].taxes,
+            vec![String::from("TVA 9%"), String::from("Local")]
+        );
+
+        assert_eq!(mapping.taxes_per_item(), Some(true));
+
+        mapping.outputs.taxes = Some(String::from("TVA 5,5%;Local"));
+        let items = mapped_items(&mapping, &task_list).unwrap();
+        assert_eq!(
+            items[0].taxes,
+            vec![String::from("TVA 5,5%"), String::from("Local")]
+        );
+
+        mapping.invoice = toml::from_str("taxes = \"TVA 20%\"").unwrap();
+        let (invoices, _) = mapping.apply_split(&task_list).unwrap();
+        assert!(mapping
+            .invoice_fields(&task_list, &invoices[0], &HashMap::new(), today())
+            .is_err());
+
+        mapping.outputs.taxes = None;
+        assert_eq!(mapping.taxes_per_item(), Some(false));
+        let fields = mapping
+            .invoice_fields(&task_list, &invoices[0], &HashMap::new(), today())
+            .unwrap();
+        assert_eq!(fields.taxes, vec![String::from("TVA 20%")]);
+    }
+
+    #[test]
+    fn apply_customer_fields() {
+        let mut mapping = get_fake_mapping();
+        mapping.customer = toml::from_str(
+            r#"
+            email = "${te}@example.com"
+            currency = "${currency}"
+            vat_number = " "
+
+            [address]
+            street = "1 ${tc} street"
+            country = "FR"
+            "#,
+        )
+        .unwrap();
+        let task_list = get_fake_task_list();
+        let variables = HashMap::from([(String::from("currency"), String::from("EUR"))]);
+
+        let (invoices, _) = mapping.apply_split(&task_list).unwrap();
+        let fields = mapping
+            .customer_fields(&task_list, &invoices[0], &variables, today())
+            .unwrap();
+
+        assert_eq!(
+            fields,
+            customer_fields::ComputedCustomerFields {
+                email: Some(String::from("yet@example.com")),
+                currency: Some(String::from("EUR")),
+                street: Some(String::from("1 some street")),
+                country: Some(String::from("FR")),
+                ..Default::default()
+            }
+        );
+
+        mapping.customer = toml::from_str("email = \"${unknown}\"").unwrap();
+        assert!(matches!(
+            mapping.customer_fields(&task_list, &invoices[0], &variables, today()),
+            Err(TemplateMappingError::FieldError(f, _)) if f == "email"
+        ));
+
+        mapping.customer = toml::from_str("vat_number = \"${today|date:%Y%m%d}\"").unwrap();
+        let fields = mapping
+            .customer_fields(&task_list, &invoices[0], &variables, today())
+            .unwrap();
+        assert_eq!(fields.vat_number.as_deref(), Some("20241104"));
+    }
+
+    #[test]
+    fn parse_customer_ref() {
+        assert_eq!(CustomerRef::parse("12"), CustomerRef::Id(12));
+        assert_eq!(
+            CustomerRef::parse("Acme"),
+            CustomerRef::Name(String::from("Acme"))
+        );
+        assert_eq!(
+            CustomerRef::parse("12 Monkeys"),
+            CustomerRef::Name(String::from("12 Monkeys"))
+        );
+    }
+
+    #[test]
+    fn apply_defaults() {
+        let mut mapping = get_fake_mapping();
+        mapping.inputs.insert(
+            String::from("unit"),
+            InputBinding::Detailed {
+                column: String::from("Unité"),
+                optional: true,
+                default: Some(String::from("hour")),
+            },
+        );
+        mapping.inputs.insert(
+            String::from("qty"),
+            InputBinding::Detailed {
+                column: String::from("Missing"),
+                optional: true,
+                default: None,
+            },
+        );
+        mapping.inputs.insert(
+            String::from("rate"),
+            InputBinding::Column(String::from("Tarif")),
+        );
+
+        let task_list = TaskList::from(
+            vec![String::from("Unité"), String::from("Tarif")],
+            vec![
+                TaskRow::new(2, vec![Value::String(String::from("day")), Value::None]),
+                TaskRow::new(3, vec![Value::None, Value::Integer(80)]),
+            ],
+        );
+
+        let line_1 = mapping.apply_line_str("${unit}", &task_list, 0).unwrap();
+        assert_eq!(line_1, "day");
+
+        let line_2 = mapping.apply_line_str("${unit}", &task_list, 1).unwrap();
+        assert_eq!(line_2, "hour");
+
+        let line_3 = mapping
+            .apply_line_str("${unit:-piece} ${qty}", &task_list, 1)
+            .unwrap();
+        assert_eq!(line_3, "piece ");
+
+        let line_4 = mapping
+            .apply_line_number("${rate:-50} + ${qty}", &task_list, 0)
+            .unwrap();
+        assert_eq!(line_4, dec!(50));
+
+        let line_5 = mapping
+            .apply_line_number("${qty:-1.5}", &task_list, 0)
+            .unwrap();
+        assert_eq!(line_5, dec!(1.5));
+
+        let line_6 = mapping.apply_line_number("${rate}", &task_list, 0);
+        assert!(matches!(
+            line_6,
+            Err(TemplateMappingError::NumberParsingError(_))
+        ));
+
+        let options = mapping.reading_options();
+        assert!(options
+            .rows
+            .optional_columns
+            .contains(&String::from("Unité")));
+        assert!(!options
+            .rows
+            .optional_columns
+            .contains(&String::from("Tarif")));
     }
 
     #[test]
@@ -411,46 +2003,129 @@ mod tests {
         let mapping = get_fake_mapping();
         let task_list = get_fake_task_list();
 
-        let items = mapping.apply(&task_list).unwrap();
+        let items = mapped_items(&mapping, &task_list).unwrap();
 
         assert_eq!(items[0].name, "$nom some");
         assert_eq!(items[0].description, "some yet");
         assert_eq!(items[0].unit_name, "yetsome");
-        assert_eq!(items[0].price, 1.1);
-        assert_eq!(items[0].total, 1.1);
-        assert_eq!(items[0].quantity, 1);
-        assert_eq!(items[0].sub_total, 1.1);
+        assert_eq!(items[0].price, dec!(1.1));
+        assert_eq!(items[0].total, dec!(1.1));
+        assert_eq!(items[0].quantity, dec!(1));
+        assert_eq!(items[0].sub_total, dec!(1.1));
 
         assert_eq!(items[1].name, "$nom things");
         assert_eq!(items[1].description, "things other");
         assert_eq!(items[1].unit_name, "otherthings");
-        assert_eq!(items[1].price, 2.0);
-        assert_eq!(items[1].total, 2.0);
-        assert_eq!(items[1].quantity, 2);
-        assert_eq!(items[1].sub_total, 2.0);
+        assert_eq!(items[1].price, dec!(2.0));
+        assert_eq!(items[1].total, dec!(2.0));
+        assert_eq!(items[1].quantity, dec!(2));
+        assert_eq!(items[1].sub_total, dec!(2.0));
 
         assert_eq!(items[2].name, "$nom in");
         assert_eq!(items[2].description, "in things");
         assert_eq!(items[2].unit_name, "thingsin");
-        assert_eq!(items[2].price, 3.3);
-        assert_eq!(items[2].total, 3.3);
-        assert_eq!(items[2].quantity, 3);
-        assert_eq!(items[2].sub_total, 3.3);
+        assert_eq!(items[2].price, dec!(3.3));
+        assert_eq!(items[2].total, dec!(3.3));
+        assert_eq!(items[2].quantity, dec!(3));
+        assert_eq!(items[2].sub_total, dec!(3.3));
 
         assert_eq!(items[3].name, "$nom an");
         assert_eq!(items[3].description, "an in");
         assert_eq!(items[3].unit_name, "inan");
-        assert_eq!(items[3].price, 9.2);
-        assert_eq!(items[3].total, 9.2);
-        assert_eq!(items[3].quantity, 9);
-        assert_eq!(items[3].sub_total, 9.2);
+        assert_eq!(items[3].price, dec!(9.2));
+        assert_eq!(items[3].total, dec!(9.2));
+        assert_eq!(items[3].quantity, dec!(9));
+        assert_eq!(items[3].sub_total, dec!(9.2));
 
         assert_eq!(items[4].name, "$nom array");
         assert_eq!(items[4].description, "array there");
         assert_eq!(items[4].unit_name, "therearray");
-        assert_eq!(items[4].price, 10.0);
-        assert_eq!(items[4].total, 10.0);
-        assert_eq!(items[4].quantity, 10);
-        assert_eq!(items[4].sub_total, 10.0);
+        assert_eq!(items[4].price, dec!(10.0));
+        assert_eq!(items[4].total, dec!(10.0));
+        assert_eq!(items[4].quantity, dec!(10));
+        assert_eq!(items[4].sub_total, dec!(10.0));
+    }
+
+    #[test]
+    fn apply_line_expression() {
+        let mapping = get_fake_mapping();
+        let task_list = get_fake_task_list();
+
+        let line_1 = mapping
+            .apply_line_number("${tf} * ${ti}", &task_list, 3)
+            .unwrap();
+        assert_eq!(line_1, dec!(82.8));
+
+        let line_2 = mapping
+            .apply_line_number("round(${tf} * 1.2, 1)", &task_list, 2)
+            .unwrap();
+        assert_eq!(line_2, dec!(4.0));
+
+        let line_3 = mapping
+            .apply_line_number("${ti} * 2 + 1", &task_list, 4)
+            .unwrap();
+        assert_eq!(line_3, dec!(21));
+
+        let line_4 = mapping
+            .apply_line_number("${tf} * 1", &task_list, 0)
+            .unwrap();
+        assert_eq!(line_4, dec!(1.1));
+    }
+
+    #[test]
+    fn apply_reports_broken_field() {
+        let mut mapping = get_fake_mapping();
+        mapping.outputs.total = String::from("${tf} *");
+        let task_list = get_fake_task_list();
+
+        let items = mapped_items(&mapping, &task_list);
+
+        if let Err(TemplateMappingError::FieldError(field, e)) = items {
+            assert_eq!(field, "total");
+            assert!(matches!(
+                *e,
+                TemplateMappingError::ExpressionError { position: 7, .. }
+            ));
+        } else {
+            panic!("Expected a FieldError, got {:?}", items);
+        }
+    }
+
+    #[test]
+    fn apply_rounds_amounts() {
+        let mut mapping = get_fake_mapping();
+        mapping.outputs.price = String::from("0.125");
+        mapping.outputs.total = String::from("2.675");
+        let task_list = get_fake_task_list();
+
+        let items = mapped_items(&mapping, &task_list).unwrap();
+
+        assert_eq!(items[0].price, dec!(0.13));
+        assert_eq!(items[0].total, dec!(2.68));
+    }
+
+    #[test]
+    fn apply_fractional_quantity() {
+        let mut mapping = get_fake_mapping();
+        mapping.outputs.quantity = String::from("${du}");
+        mapping.quantity_precision = 1;
+        let task_list = get_fake_task_list();
+
+        let items = mapped_items(&mapping, &task_list).unwrap();
+
+        assert_eq!(items[1].quantity, dec!(0.5));
+        assert_eq!(items[2].quantity, dec!(0.8));
+        assert_eq!(items[3].quantity, dec!(2.3));
+    }
+
+    #[test]
+    fn apply_names_failing_row() {
+        let mut mapping = get_fake_mapping();
+        mapping.outputs.price = String::from("${tc}");
+        let task_list = get_fake_task_list();
+
+        let items = mapped_items(&mapping, &task_list);
+
+        assert!(matches!(items, Err(TemplateMappingError::RowError(2, _))));
     }
 }
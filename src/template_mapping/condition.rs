@@ -0,0 +1,539 @@
+use std::cmp::Ordering;
+
+use crate::spreadsheet_parsing::spreadsheet_data::Value;
+
+use super::TemplateMappingError;
+
+/// Condition on the values of a row, such as `type == 'travel' && ${hours} > 2`.
+/// Inputs are written either by name or as a `${...}` placeholder.
+#[derive(Debug)]
+pub enum Condition {
+    Literal(Value),
+    /// Input name or content of a `${...}` placeholder.
+    Placeholder(String),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    Compare(String, Box<Condition>, Box<Condition>),
+    /// `condition ? then : otherwise`
+    Choice(Box<Condition>, Box<Condition>, Box<Condition>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(String),
+    Number(f64),
+    Bool(bool),
+    Placeholder(String),
+    Identifier(String),
+    Operator(String),
+    OpenParen,
+    CloseParen,
+}
+
+fn error(position: usize, message: &str) -> TemplateMappingError {
+    return TemplateMappingError::ExpressionError {
+        position,
+        message: String::from(message),
+    };
+}
+
+const OPERATORS: [&str; 11] = ["==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "?", ":"];
+
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>, TemplateMappingError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        if c == '$' {
+            if chars.get(i + 1) != Some(&'{') {
+                return Err(error(i, "Expected '{' after '$'"));
+            }
+
+            let end = chars[i..].iter().position(|c| *c == '}');
+
+            if let None = end {
+                return Err(error(i, "Unclosed placeholder"));
+            }
+
+            let end = i + end.unwrap();
+            tokens.push((
+                Token::Placeholder(chars[i + 2..end].iter().collect()),
+                start,
+            ));
+            i = end + 1;
+            continue;
+        }
+
+        if c == '\'' || c == '"' {
+            let end = chars[i + 1..].iter().position(|q| *q == c);
+
+            if let None = end {
+                return Err(error(i, "Unclosed string"));
+            }
+
+            let end = i + 1 + end.unwrap();
+            tokens.push((Token::Literal(chars[i + 1..end].iter().collect()), start));
+            i = end + 1;
+            continue;
+        }
+
+        let negative_number = c == '-' && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit());
+
+        if c.is_ascii_digit() || negative_number {
+            i += 1;
+
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+
+            let literal: String = chars[start..i].iter().collect();
+            let number = literal.parse::<f64>();
+
+            if let Err(_) = number {
+                return Err(error(start, "Invalid number"));
+            }
+
+            tokens.push((Token::Number(number.unwrap()), start));
+            continue;
+        }
+
+        if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+
+            let word: String = chars[start..i].iter().collect();
+
+            let token = match word.as_str() {
+                "true" => Token::Bool(true),
+                "false" => Token::Bool(false),
+                _ => Token::Identifier(word),
+            };
+
+            tokens.push((token, start));
+            continue;
+        }
+
+        if c == '(' || c == ')' {
+            let token = if c == '(' {
+                Token::OpenParen
+            } else {
+                Token::CloseParen
+            };
+
+            tokens.push((token, start));
+            i += 1;
+            continue;
+        }
+
+        let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+        let operator = OPERATORS.iter().find(|o| rest.starts_with(*o));
+
+        if let None = operator {
+            return Err(error(i, &format!("Unexpected character '{}'", c)));
+        }
+
+        let operator = operator.unwrap();
+        tokens.push((Token::Operator(String::from(*operator)), start));
+        i += operator.len();
+    }
+
+    return Ok(tokens);
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    current: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        return self.tokens.get(self.current).map(|(t, _)| t);
+    }
+
+    fn position(&self) -> usize {
+        return self
+            .tokens
+            .get(self.current)
+            .map(|(_, p)| *p)
+            .unwrap_or(self.end);
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.current).map(|(t, _)| t.clone());
+        self.current += 1;
+        return token;
+    }
+
+    fn is_operator(&self, operators: &[&str]) -> Option<String> {
+        match self.peek() {
+            Some(Token::Operator(o)) if operators.contains(&o.as_str()) => return Some(o.clone()),
+            _ => return None,
+        }
+    }
+
+    fn expect(&mut self, expected: Token, message: &str) -> Result<(), TemplateMappingError> {
+        if self.peek() != Some(&expected) {
+            return Err(error(self.position(), message));
+        }
+
+        self.current += 1;
+        return Ok(());
+    }
+
+    /// choice := or ('?' choice ':' choice)?
+    fn choice(&mut self) -> Result<Condition, TemplateMappingError> {
+        let condition = self.or();
+
+        if let Err(e) = condition {
+            return Err(e);
+        }
+
+        if let None = self.is_operator(&["?"]) {
+            return condition;
+        }
+
+        self.current += 1;
+        let then = self.choice();
+
+        if let Err(e) = then {
+            return Err(e);
+        }
+
+        if let Err(e) = self.expect(Token::Operator(String::from(":")), "Expected ':'") {
+            return Err(e);
+        }
+
+        let otherwise = self.choice();
+
+        if let Err(e) = otherwise {
+            return Err(e);
+        }
+
+        return Ok(Condition::Choice(
+            Box::new(condition.unwrap()),
+            Box::new(then.unwrap()),
+            Box::new(otherwise.unwrap()),
+        ));
+    }
+
+    /// or := and ('||' and)*
+    fn or(&mut self) -> Result<Condition, TemplateMappingError> {
+        let left = self.and();
+
+        if let Err(e) = left {
+            return Err(e);
+        }
+
+        let mut left = left.unwrap();
+
+        while let Some(_) = self.is_operator(&["||"]) {
+            self.current += 1;
+            let right = self.and();
+
+            if let Err(e) = right {
+                return Err(e);
+            }
+
+            left = Condition::Or(Box::new(left), Box::new(right.unwrap()));
+        }
+
+        return Ok(left);
+    }
+
+    /// and := not ('&&' not)*
+    fn and(&mut self) -> Result<Condition, TemplateMappingError> {
+        let left = self.not();
+
+        if let Err(e) = left {
+            return Err(e);
+        }
+
+        let mut left = left.unwrap();
+
+        while let Some(_) = self.is_operator(&["&&"]) {
+            self.current += 1;
+            let right = self.not();
+
+            if let Err(e) = right {
+                return Err(e);
+            }
+
+            left = Condition::And(Box::new(left), Box::new(right.unwrap()));
+        }
+
+        return Ok(left);
+    }
+
+    /// not := '!' not | comparison
+    fn not(&mut self) -> Result<Condition, TemplateMappingError> {
+        if let Some(_) = self.is_operator(&["!"]) {
+            self.current += 1;
+            return self.not().map(|c| Condition::Not(Box::new(c)));
+        }
+
+        return self.comparison();
+    }
+
+    /// comparison := operand (('==' | '!=' | '<' | '<=' | '>' | '>=') operand)?
+    fn comparison(&mut self) -> Result<Condition, TemplateMappingError> {
+        let left = self.operand();
+
+        if let Err(e) = left {
+            return Err(e);
+        }
+
+        let operator = self.is_operator(&["==", "!=", "<", "<=", ">", ">="]);
+
+        if let None = operator {
+            return left;
+        }
+
+        self.current += 1;
+        let right = self.operand();
+
+        if let Err(e) = right {
+            return Err(e);
+        }
+
+        return Ok(Condition::Compare(
+            operator.unwrap(),
+            Box::new(left.unwrap()),
+            Box::new(right.unwrap()),
+        ));
+    }
+
+    /// operand := string | number | boolean | name | placeholder | '(' choice ')'
+    fn operand(&mut self) -> Result<Condition, TemplateMappingError> {
+        let position = self.position();
+
+        match self.next() {
+            Some(Token::Literal(s)) => return Ok(Condition::Literal(Value::String(s))),
+            Some(Token::Number(n)) => return Ok(Condition::Literal(Value::Float(n))),
+            Some(Token::Bool(b)) => return Ok(Condition::Literal(Value::Bool(b))),
+            Some(Token::Identifier(name)) | Some(Token::Placeholder(name)) => {
+                return Ok(Condition::Placeholder(name))
+            }
+            Some(Token::OpenParen) => {
+                let inner = self.choice();
+
+                if let Err(e) = inner {
+                    return Err(e);
+                }
+
+                if let Err(e) = self.expect(Token::CloseParen, "Expected ')'") {
+                    return Err(e);
+                }
+
+                return inner;
+            }
+            Some(_) => return Err(error(position, "Unexpected token")),
+            None => return Err(error(position, "Unexpected end of condition")),
+        }
+    }
+}
+
+/// Whether a value counts as true on its own, as in `${if billable}`.
+pub fn is_true(value: &Value) -> bool {
+    match value {
+        Value::Bool(b) => return *b,
+        Value::None => return false,
+        Value::String(s) => {
+            let s = s.trim();
+            return !s.is_empty() && s != "0" && !s.eq_ignore_ascii_case("false");
+        }
+        _ => return value.as_number().map(|n| n != 0.0).unwrap_or(true),
+    }
+}
+
+/// Compares as numbers when both sides are numbers, and as text otherwise.
+fn compare(operator: &str, left: &Value, right: &Value) -> bool {
+    let ordering = match (left.as_number(), right.as_number()) {
+        (Some(l), Some(r)) => l.partial_cmp(&r),
+        _ => Some(left.to_string().cmp(&right.to_string())),
+    };
+
+    match (operator, ordering) {
+        ("==", o) => return o == Some(Ordering::Equal),
+        ("!=", o) => return o != Some(Ordering::Equal),
+        (_, None) => return false,
+        ("<", Some(o)) => return o == Ordering::Less,
+        ("<=", Some(o)) => return o != Ordering::Greater,
+        (">", Some(o)) => return o == Ordering::Greater,
+        (_, Some(o)) => return o != Ordering::Less,
+    }
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Condition, TemplateMappingError> {
+        let tokens = tokenize(text);
+
+        if let Err(e) = tokens {
+            return Err(e);
+        }
+
+        let mut parser = Parser {
+            tokens: tokens.unwrap(),
+            current: 0,
+            end: text.chars().count(),
+        };
+
+        let condition = parser.choice();
+
+        if condition.is_ok() && parser.current < parser.tokens.len() {
+            return Err(error(parser.position(), "Unexpected token"));
+        }
+
+        return condition;
+    }
+
+    /// Computes the condition, `resolve` giving the value behind each input.
+    /// Comparisons give booleans, and a choice gives the value of its branch.
+    pub fn eval(
+        &self,
+        resolve: &dyn Fn(&str) -> Result<Value, TemplateMappingError>,
+    ) -> Result<Value, TemplateMappingError> {
+        match self {
+            Condition::Literal(v) => return Ok(v.clone()),
+            Condition::Placeholder(p) => return resolve(p),
+            Condition::Not(c) => return c.eval(resolve).map(|v| Value::Bool(!is_true(&v))),
+            Condition::And(left, right) | Condition::Or(left, right) => {
+                let left = left.eval(resolve);
+
+                if let Err(e) = left {
+                    return Err(e);
+                }
+
+                let left = is_true(&left.unwrap());
+
+                // Only looks at the right side when it matters.
+                if left != matches!(self, Condition::And(..)) {
+                    return Ok(Value::Bool(left));
+                }
+
+                return right.eval(resolve).map(|v| Value::Bool(is_true(&v)));
+            }
+            Condition::Compare(operator, left, right) => {
+                let (left, right) = (left.eval(resolve), right.eval(resolve));
+
+                if let Err(e) = left {
+                    return Err(e);
+                }
+
+                if let Err(e) = right {
+                    return Err(e);
+                }
+
+                return Ok(Value::Bool(compare(
+                    operator,
+                    &left.unwrap(),
+                    &right.unwrap(),
+                )));
+            }
+            Condition::Choice(condition, then, otherwise) => {
+                let condition = condition.eval(resolve);
+
+                if let Err(e) = condition {
+                    return Err(e);
+                }
+
+                if is_true(&condition.unwrap()) {
+                    return then.eval(resolve);
+                }
+
+                return otherwise.eval(resolve);
+            }
+        }
+    }
+
+    /// Whether the condition holds for the values given by `resolve`.
+    pub fn holds(
+        &self,
+        resolve: &dyn Fn(&str) -> Result<Value, TemplateMappingError>,
+    ) -> Result<bool, TemplateMappingError> {
+        return self.eval(resolve).map(|v| is_true(&v));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(text: &str) -> Result<Value, TemplateMappingError> {
+        let resolve = |p: &str| match p {
+            "type" => Ok(Value::String(String::from("travel"))),
+            "hours" => Ok(Value::Float(1.5)),
+            "count" => Ok(Value::String(String::from("10"))),
+            "billable" => Ok(Value::Bool(true)),
+            "note" => Ok(Value::None),
+            _ => Err(TemplateMappingError::TemplateMappingError(String::from(p))),
+        };
+
+        return Condition::parse(text).and_then(|c| c.eval(&resolve));
+    }
+
+    fn holds(text: &str) -> bool {
+        return is_true(&eval(text).unwrap());
+    }
+
+    #[test]
+    fn comparisons() {
+        assert!(holds("type == 'travel'"));
+        assert!(holds("${type} != \"work\""));
+        assert!(holds("hours > 1 && hours <= 1.5"));
+        assert!(holds("count > 9"));
+        assert!(holds("count == 10"));
+        assert!(holds("'b' > 'a'"));
+        assert!(holds("billable == true"));
+        assert!(holds("hours >= -2"));
+        assert!(!holds("note"));
+        assert!(holds("!note && (billable || unknown)"));
+        assert!(holds("note == ''"));
+    }
+
+    #[test]
+    fn choices() {
+        assert_eq!(
+            eval("type == 'travel' ? 'km' : 'h'").unwrap().to_string(),
+            "km"
+        );
+        assert_eq!(eval("hours > 2 ? 1 : hours").unwrap().to_string(), "1.5");
+        assert_eq!(
+            eval("note ? 'a' : billable ? 'b' : 'c'")
+                .unwrap()
+                .to_string(),
+            "b"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let position = |text: &str| match eval(text) {
+            Err(TemplateMappingError::ExpressionError { position, .. }) => position,
+            other => panic!("Expected a condition error, got {:?}", other),
+        };
+
+        assert_eq!(position("type = 'travel'"), 5);
+        assert_eq!(position("type == 'travel"), 8);
+        assert_eq!(position("type =="), 7);
+        assert_eq!(position("(hours > 1"), 10);
+        assert_eq!(position("hours ? 1"), 9);
+        assert_eq!(position("hours 1"), 6);
+
+        assert!(matches!(
+            eval("unknown == 1"),
+            Err(TemplateMappingError::TemplateMappingError(_))
+        ));
+    }
+}
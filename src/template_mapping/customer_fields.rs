@@ -0,0 +1,121 @@
+use serde::Deserialize;
+
+use crate::spreadsheet_parsing::spreadsheet_data::TaskList;
+
+use super::{TemplateMapping, TemplateMappingError};
+
+/// Details of the customers created during an import, set under `[customer]` in the
+/// template. Fields take the same placeholders as `[invoice]`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CustomerTemplate {
+    pub email: Option<String>,
+    /// Currency code, such as `EUR`.
+    pub currency: Option<String>,
+    pub vat_number: Option<String>,
+    #[serde(default)]
+    pub address: AddressTemplate,
+}
+
+/// Billing address of the customer, under `[customer.address]`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AddressTemplate {
+    pub street: Option<String>,
+    pub street_2: Option<String>,
+    pub city: Option<String>,
+    pub state: Option<String>,
+    pub zip: Option<String>,
+    /// Country code, such as `FR`.
+    pub country: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ComputedCustomerFields {
+    pub email: Option<String>,
+    pub currency: Option<String>,
+    pub vat_number: Option<String>,
+    pub street: Option<String>,
+    pub street_2: Option<String>,
+    pub city: Option<String>,
+    pub state: Option<String>,
+    pub zip: Option<String>,
+    pub country: Option<String>,
+}
+
+impl CustomerTemplate {
+    /// Computes the customer fields against the row built for the `[invoice]` fields.
+    pub fn compute(
+        &self,
+        mapping: &TemplateMapping,
+        data: &TaskList,
+    ) -> Result<ComputedCustomerFields, TemplateMappingError> {
+        let text = |field: &str, line: &Option<String>| match line {
+            Some(l) => mapping
+                .apply_line_str(l, data, 0)
+                .map(|t| Some(String::from(t.trim())).filter(|t| !t.is_empty()))
+                .map_err(|e| e.in_field(field)),
+            None => Ok(None),
+        };
+
+        let (email, currency, vat_number) = (
+            text("email", &self.email),
+            text("currency", &self.currency),
+            text("vat_number", &self.vat_number),
+        );
+        let (street, street_2, city, state, zip, country) = (
+            text("address.street", &self.address.street),
+            text("address.street_2", &self.address.street_2),
+            text("address.city", &self.address.city),
+            text("address.state", &self.address.state),
+            text("address.zip", &self.address.zip),
+            text("address.country", &self.address.country),
+        );
+
+        if email.is_err() {
+            return Err(email.unwrap_err());
+        }
+
+        if currency.is_err() {
+            return Err(currency.unwrap_err());
+        }
+
+        if vat_number.is_err() {
+            return Err(vat_number.unwrap_err());
+        }
+
+        if street.is_err() {
+            return Err(street.unwrap_err());
+        }
+
+        if street_2.is_err() {
+            return Err(street_2.unwrap_err());
+        }
+
+        if city.is_err() {
+            return Err(city.unwrap_err());
+        }
+
+        if state.is_err() {
+            return Err(state.unwrap_err());
+        }
+
+        if zip.is_err() {
+            return Err(zip.unwrap_err());
+        }
+
+        if country.is_err() {
+            return Err(country.unwrap_err());
+        }
+
+        return Ok(ComputedCustomerFields {
+            email: email.unwrap(),
+            currency: currency.unwrap(),
+            vat_number: vat_number.unwrap(),
+            street: street.unwrap(),
+            street_2: street_2.unwrap(),
+            city: city.unwrap(),
+            state: state.unwrap(),
+            zip: zip.unwrap(),
+            country: country.unwrap(),
+        });
+    }
+}
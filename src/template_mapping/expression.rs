@@ -0,0 +1,442 @@
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+
+use crate::money;
+
+use super::TemplateMappingError;
+
+/// Arithmetic expression of a numeric output field, such as `round(${minutes} / 60, 2)`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Number(Decimal),
+    /// Content of a `${...}` placeholder, with its position in the field.
+    Placeholder(String, usize),
+    Negate(Box<Expression>),
+    Binary(char, Box<Expression>, Box<Expression>, usize),
+    Call(String, Vec<Expression>, usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(Decimal),
+    Placeholder(String),
+    Identifier(String),
+    Operator(char),
+    OpenParen,
+    CloseParen,
+    Comma,
+}
+
+fn error(position: usize, message: &str) -> TemplateMappingError {
+    return TemplateMappingError::ExpressionError {
+        position,
+        message: String::from(message),
+    };
+}
+
+/// Splits a field into tokens, each with its character position.
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>, TemplateMappingError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        if c == '$' {
+            if chars.get(i + 1) != Some(&'{') {
+                return Err(error(i, "Expected '{' after '$'"));
+            }
+
+            // Braces nested in filter or default arguments, as in `${qty:-{1}}`, belong
+            // to the placeholder.
+            let mut depth = 0;
+            let end = chars[i + 1..].iter().position(|c| {
+                match c {
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    _ => (),
+                }
+
+                return depth == 0;
+            });
+
+            if let None = end {
+                return Err(error(i, "Unclosed placeholder"));
+            }
+
+            let end = i + 1 + end.unwrap();
+            let content: String = chars[i + 2..end].iter().collect();
+            tokens.push((Token::Placeholder(content), start));
+            i = end + 1;
+            continue;
+        }
+
+        if c.is_ascii_digit() || c == '.' {
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+
+            let literal: String = chars[start..i].iter().collect();
+            let number = Decimal::from_str(&literal);
+
+            if let Err(_) = number {
+                return Err(error(start, "Invalid number"));
+            }
+
+            tokens.push((Token::Number(number.unwrap()), start));
+            continue;
+        }
+
+        if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+
+            tokens.push((Token::Identifier(chars[start..i].iter().collect()), start));
+            continue;
+        }
+
+        let token = match c {
+            '+' | '-' | '*' | '/' => Token::Operator(c),
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            ',' => Token::Comma,
+            _ => return Err(error(i, &format!("Unexpected character '{}'", c))),
+        };
+
+        tokens.push((token, start));
+        i += 1;
+    }
+
+    return Ok(tokens);
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    current: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        return self.tokens.get(self.current).map(|(t, _)| t);
+    }
+
+    fn position(&self) -> usize {
+        return self
+            .tokens
+            .get(self.current)
+            .map(|(_, p)| *p)
+            .unwrap_or(self.end);
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.current).map(|(t, _)| t.clone());
+        self.current += 1;
+        return token;
+    }
+
+    fn expect(&mut self, expected: Token, message: &str) -> Result<(), TemplateMappingError> {
+        if self.peek() != Some(&expected) {
+            return Err(error(self.position(), message));
+        }
+
+        self.current += 1;
+        return Ok(());
+    }
+
+    /// sum := product (('+' | '-') product)*
+    fn sum(&mut self) -> Result<Expression, TemplateMappingError> {
+        let left = self.product();
+
+        if let Err(e) = left {
+            return Err(e);
+        }
+
+        let mut left = left.unwrap();
+
+        while let Some(Token::Operator(op @ ('+' | '-'))) = self.peek().cloned() {
+            let position = self.position();
+            self.current += 1;
+
+            let right = self.product();
+
+            if let Err(e) = right {
+                return Err(e);
+            }
+
+            left = Expression::Binary(op, Box::new(left), Box::new(right.unwrap()), position);
+        }
+
+        return Ok(left);
+    }
+
+    /// product := unary (('*' | '/') unary)*
+    fn product(&mut self) -> Result<Expression, TemplateMappingError> {
+        let left = self.unary();
+
+        if let Err(e) = left {
+            return Err(e);
+        }
+
+        let mut left = left.unwrap();
+
+        while let Some(Token::Operator(op @ ('*' | '/'))) = self.peek().cloned() {
+            let position = self.position();
+            self.current += 1;
+
+            let right = self.unary();
+
+            if let Err(e) = right {
+                return Err(e);
+            }
+
+            left = Expression::Binary(op, Box::new(left), Box::new(right.unwrap()), position);
+        }
+
+        return Ok(left);
+    }
+
+    /// unary := '-' unary | primary
+    fn unary(&mut self) -> Result<Expression, TemplateMappingError> {
+        if let Some(Token::Operator('-')) = self.peek() {
+            self.current += 1;
+            return self.unary().map(|e| Expression::Negate(Box::new(e)));
+        }
+
+        return self.primary();
+    }
+
+    /// primary := number | placeholder | identifier '(' arguments ')' | '(' sum ')'
+    fn primary(&mut self) -> Result<Expression, TemplateMappingError> {
+        let position = self.position();
+
+        match self.next() {
+            Some(Token::Number(n)) => return Ok(Expression::Number(n)),
+            Some(Token::Placeholder(p)) => return Ok(Expression::Placeholder(p, position)),
+            Some(Token::OpenParen) => {
+                let inner = self.sum();
+
+                if let Err(e) = inner {
+                    return Err(e);
+                }
+
+                if let Err(e) = self.expect(Token::CloseParen, "Expected ')'") {
+                    return Err(e);
+                }
+
+                return inner;
+            }
+            Some(Token::Identifier(name)) => {
+                if let Err(e) = self.expect(Token::OpenParen, "Expected '(' after a function name")
+                {
+                    return Err(e);
+                }
+
+                let mut arguments = Vec::new();
+
+                while self.peek() != Some(&Token::CloseParen) {
+                    if !arguments.is_empty() {
+                        if let Err(e) = self.expect(Token::Comma, "Expected ',' or ')'") {
+                            return Err(e);
+                        }
+                    }
+
+                    let argument = self.sum();
+
+                    if let Err(e) = argument {
+                        return Err(e);
+                    }
+
+                    arguments.push(argument.unwrap());
+                }
+
+                self.current += 1;
+                return Ok(Expression::Call(name, arguments, position));
+            }
+            Some(_) => return Err(error(position, "Unexpected token")),
+            None => return Err(error(position, "Unexpected end of expression")),
+        }
+    }
+}
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Expression, TemplateMappingError> {
+        let tokens = tokenize(text);
+
+        if let Err(e) = tokens {
+            return Err(e);
+        }
+
+        let mut parser = Parser {
+            tokens: tokens.unwrap(),
+            current: 0,
+            end: text.chars().count(),
+        };
+
+        let expression = parser.sum();
+
+        if expression.is_ok() && parser.current < parser.tokens.len() {
+            return Err(error(parser.position(), "Unexpected token"));
+        }
+
+        return expression;
+    }
+
+    /// Computes the expression, `resolve` giving the number behind each placeholder.
+    pub fn eval(
+        &self,
+        resolve: &dyn Fn(&str) -> Result<Decimal, TemplateMappingError>,
+    ) -> Result<Decimal, TemplateMappingError> {
+        match self {
+            Expression::Number(n) => return Ok(*n),
+            Expression::Placeholder(p, _) => return resolve(p),
+            Expression::Negate(e) => return e.eval(resolve).map(|v| -v),
+            Expression::Binary(op, left, right, position) => {
+                let (left, right) = (left.eval(resolve), right.eval(resolve));
+
+                if let Err(e) = left {
+                    return Err(e);
+                }
+
+                if let Err(e) = right {
+                    return Err(e);
+                }
+
+                let (left, right) = (left.unwrap(), right.unwrap());
+
+                let result = match op {
+                    '+' => left.checked_add(right),
+                    '-' => left.checked_sub(right),
+                    '*' => left.checked_mul(right),
+                    _ if right.is_zero() => return Err(error(*position, "Division by zero")),
+                    _ => left.checked_div(right),
+                };
+
+                if let None = result {
+                    return Err(error(*position, "Overflow"));
+                }
+
+                return Ok(result.unwrap());
+            }
+            Expression::Call(name, arguments, position) => {
+                let mut values = Vec::new();
+
+                for a in arguments {
+                    let value = a.eval(resolve);
+
+                    if let Err(e) = value {
+                        return Err(e);
+                    }
+
+                    values.push(value.unwrap());
+                }
+
+                return call(name, &values, *position);
+            }
+        }
+    }
+}
+
+fn call(name: &str, values: &[Decimal], position: usize) -> Result<Decimal, TemplateMappingError> {
+    match (name, values) {
+        ("round", [x]) => return Ok(money::round(*x, 0)),
+        ("round", [x, digits]) => {
+            if digits.is_sign_negative() || !digits.fract().is_zero() {
+                return Err(error(position, "round() takes a whole number of digits"));
+            }
+
+            return Ok(money::round(*x, digits.trunc().try_into().unwrap_or(0)));
+        }
+        ("floor", [x]) => return Ok(x.floor()),
+        ("ceil", [x]) => return Ok(x.ceil()),
+        ("abs", [x]) => return Ok(x.abs()),
+        ("min", [first, ..]) => return Ok(values.iter().fold(*first, |a, b| a.min(*b))),
+        ("max", [first, ..]) => return Ok(values.iter().fold(*first, |a, b| a.max(*b))),
+        ("round" | "floor" | "ceil" | "abs" | "min" | "max", _) => {
+            return Err(error(
+                position,
+                &format!("Wrong number of arguments for {}()", name),
+            ))
+        }
+        _ => return Err(error(position, &format!("Unknown function {}()", name))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    fn eval(text: &str) -> Result<Decimal, TemplateMappingError> {
+        let resolve = |p: &str| match p {
+            "hours" => Ok(dec!(1.5)),
+            "rate" | "rate:-{1}" => Ok(dec!(80)),
+            "minutes" => Ok(dec!(100)),
+            _ => Err(TemplateMappingError::TemplateMappingError(String::from(p))),
+        };
+
+        return Expression::parse(text).and_then(|e| e.eval(&resolve));
+    }
+
+    fn error_position(text: &str) -> usize {
+        match eval(text) {
+            Err(TemplateMappingError::ExpressionError { position, .. }) => return position,
+            other => panic!("Expected an expression error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(eval("1").unwrap(), dec!(1));
+        assert_eq!(eval("${rate} * 1.2").unwrap(), dec!(96));
+        assert_eq!(eval("${hours} * ${rate}").unwrap(), dec!(120));
+        assert_eq!(eval("1 + 2 * 3").unwrap(), dec!(7));
+        assert_eq!(eval("(1 + 2) * 3").unwrap(), dec!(9));
+        assert_eq!(eval("-${hours} - -1").unwrap(), dec!(-0.5));
+        assert_eq!(eval("0.1 + 0.2").unwrap(), dec!(0.3));
+    }
+
+    #[test]
+    fn nested_braces() {
+        assert_eq!(eval("${rate:-{1}} * 2").unwrap(), dec!(160));
+        assert_eq!(error_position("${rate:-{1} * 2"), 0);
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(eval("round(${minutes} / 60, 2)").unwrap(), dec!(1.67));
+        assert_eq!(eval("round(2.5)").unwrap(), dec!(3));
+        assert_eq!(eval("floor(1.7) + ceil(1.2)").unwrap(), dec!(3));
+        assert_eq!(eval("abs(-4)").unwrap(), dec!(4));
+        assert_eq!(eval("min(3, ${hours}, 2)").unwrap(), dec!(1.5));
+        assert_eq!(eval("max(3, ${rate})").unwrap(), dec!(80));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error_position("1 +"), 3);
+        assert_eq!(error_position("${hours} ${rate}"), 9);
+        assert_eq!(error_position("(1 + 2"), 6);
+        assert_eq!(error_position("2 % 3"), 2);
+        assert_eq!(error_position("sqrt(4)"), 0);
+        assert_eq!(error_position("round(1, 2, 3)"), 0);
+        assert_eq!(error_position("1 / (2 - 2)"), 2);
+        assert_eq!(error_position(""), 0);
+        assert_eq!(error_position("${hours"), 0);
+
+        assert!(matches!(
+            eval("${unknown} * 2"),
+            Err(TemplateMappingError::TemplateMappingError(_))
+        ));
+    }
+}
@@ -0,0 +1,391 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use chrono::Duration;
+use rust_decimal::Decimal;
+
+use crate::money;
+use crate::spreadsheet_parsing::spreadsheet_data::Value;
+
+use super::TemplateMappingError;
+
+/// Converts a value with the filter written after a `|` in a placeholder, such as
+/// `${time|hours}`. It gets what follows the filter name and a `:`, if anything.
+pub type Filter = Box<dyn Fn(Option<&str>, Value) -> Result<Value, TemplateMappingError>>;
+
+/// Filters usable in placeholders, by name.
+pub struct Filters {
+    filters: HashMap<String, Filter>,
+}
+
+impl Default for Filters {
+    fn default() -> Self {
+        let mut filters = Filters {
+            filters: HashMap::new(),
+        };
+
+        filters.register("hours", hours);
+        filters.register("minutes", minutes);
+        filters.register("date", date);
+        filters.register("number", number);
+        filters.register("upper", upper);
+        filters.register("lower", lower);
+        filters.register("trim", trim);
+        filters.register("truncate", truncate);
+
+        return filters;
+    }
+}
+
+impl fmt::Debug for Filters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<&String> = self.filters.keys().collect();
+        names.sort();
+
+        return f.debug_tuple("Filters").field(&names).finish();
+    }
+}
+
+impl Filters {
+    /// Adds a filter, replacing any other one with the same name.
+    pub fn register(
+        &mut self,
+        name: &str,
+        filter: impl Fn(Option<&str>, Value) -> Result<Value, TemplateMappingError> + 'static,
+    ) {
+        self.filters.insert(String::from(name), Box::new(filter));
+    }
+
+    pub fn apply(
+        &self,
+        name: &str,
+        argument: Option<&str>,
+        value: Value,
+    ) -> Result<Value, TemplateMappingError> {
+        let filter = self.filters.get(name);
+
+        if let None = filter {
+            return Err(TemplateMappingError::TemplateMappingError(
+                String::from("Unknown filter ") + name,
+            ));
+        }
+
+        return filter.unwrap()(argument, value);
+    }
+}
+
+fn argument_error(filter: &str, message: &str) -> TemplateMappingError {
+    return TemplateMappingError::TemplateMappingError(format!(
+        "The {} filter {}",
+        filter, message
+    ));
+}
+
+fn no_argument(filter: &str, argument: Option<&str>) -> Result<(), TemplateMappingError> {
+    if let Some(_) = argument {
+        return Err(argument_error(filter, "takes no argument"));
+    }
+
+    return Ok(());
+}
+
+fn as_duration(value: &Value) -> Result<Duration, TemplateMappingError> {
+    // Empty cells count as no time spent.
+    if value.is_empty() {
+        return Ok(Duration::zero());
+    }
+
+    let duration = value.as_duration();
+
+    if let None = duration {
+        return Err(TemplateMappingError::NumberParsingError(format!(
+            "Not a duration: {}",
+            value.to_string()
+        )));
+    }
+
+    return Ok(duration.unwrap());
+}
+
+/// `${time|hours}`: a duration as a decimal number of hours.
+fn hours(argument: Option<&str>, value: Value) -> Result<Value, TemplateMappingError> {
+    if let Err(e) = no_argument("hours", argument) {
+        return Err(e);
+    }
+
+    return as_duration(&value).map(|d| Value::Float(d.num_milliseconds() as f64 / 3_600_000.0));
+}
+
+/// `${time|minutes}`: a duration as a number of minutes.
+fn minutes(argument: Option<&str>, value: Value) -> Result<Value, TemplateMappingError> {
+    if let Err(e) = no_argument("minutes", argument) {
+        return Err(e);
+    }
+
+    return as_duration(&value).map(|d| {
+        if d.num_milliseconds() % 60_000 == 0 {
+            Value::Integer(d.num_minutes())
+        } else {
+            Value::Float(d.num_milliseconds() as f64 / 60_000.0)
+        }
+    });
+}
+
+/// `${day|date:%d/%m/%Y}`: a date in the given strftime format, `%Y-%m-%d` by default.
+/// Plain numbers are read as spreadsheet date serials.
+fn date(argument: Option<&str>, value: Value) -> Result<Value, TemplateMappingError> {
+    let format = argument.unwrap_or("%Y-%m-%d");
+
+    let date = match &value {
+        Value::None => return Ok(Value::None),
+        _ => value.as_date(),
+    };
+
+    if let None = date {
+        return Err(TemplateMappingError::TemplateMappingError(
+            String::from("Not a date: ") + &value.to_string(),
+        ));
+    }
+
+    let mut formatted = String::new();
+
+    if let Err(_) = fmt::write(
+        &mut formatted,
+        format_args!("{}", date.unwrap().format(format)),
+    ) {
+        return Err(argument_error("date", "got an invalid format"));
+    }
+
+    return Ok(Value::String(formatted));
+}
+
+/// `${cost|number:2}` or `${hours|number:1,fr}`: a number rounded half-up to the given
+/// decimals, written the way the locale does (`fr` or `en`) or plainly by default.
+fn number(argument: Option<&str>, value: Value) -> Result<Value, TemplateMappingError> {
+    if value.is_empty() {
+        return Ok(Value::None);
+    }
+
+    let (decimals, locale) = match argument.map(|a| a.split_once(',')) {
+        Some(Some((d, l))) => (Some(d.trim()), Some(l.trim())),
+        Some(None) => (argument.map(|a| a.trim()), None),
+        None => (None, None),
+    };
+
+    let text = value.to_number_string();
+    let number = Decimal::from_str(text.trim()).or(Decimal::from_scientific(text.trim()));
+
+    if let Err(_) = number {
+        return Err(TemplateMappingError::NumberParsingError(text));
+    }
+
+    let mut number = number.unwrap();
+
+    if let Some(d) = decimals.filter(|d| !d.is_empty()) {
+        let decimals = d.parse::<u32>();
+
+        if let Err(_) = decimals {
+            return Err(argument_error("number", "takes a number of decimals"));
+        }
+
+        let decimals = decimals.unwrap();
+
+        number = money::round(number, decimals);
+        number.rescale(decimals);
+    }
+
+    let (thousands, decimal_point) = match locale {
+        None => ("", "."),
+        Some("en") => (",", "."),
+        // French groups thousands with a narrow no-break space.
+        Some("fr") => ("\u{202f}", ","),
+        Some(l) => return Err(argument_error("number", &format!("has no locale {}", l))),
+    };
+
+    let text = number.to_string();
+    let (sign, text) = match text.strip_prefix('-') {
+        Some(t) => ("-", t),
+        None => ("", text.as_str()),
+    };
+    let (integer, fraction) = match text.split_once('.') {
+        Some((i, f)) => (i, Some(f)),
+        None => (text, None),
+    };
+
+    let mut grouped = String::new();
+
+    for (i, c) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            grouped.push_str(thousands);
+        }
+
+        grouped.push(c);
+    }
+
+    let mut res = String::from(sign) + &grouped;
+
+    if let Some(f) = fraction {
+        res.push_str(decimal_point);
+        res.push_str(f);
+    }
+
+    return Ok(Value::String(res));
+}
+
+fn upper(argument: Option<&str>, value: Value) -> Result<Value, TemplateMappingError> {
+    return no_argument("upper", argument).map(|_| Value::String(value.to_string().to_uppercase()));
+}
+
+fn lower(argument: Option<&str>, value: Value) -> Result<Value, TemplateMappingError> {
+    return no_argument("lower", argument).map(|_| Value::String(value.to_string().to_lowercase()));
+}
+
+fn trim(argument: Option<&str>, value: Value) -> Result<Value, TemplateMappingError> {
+    return no_argument("trim", argument)
+        .map(|_| Value::String(String::from(value.to_string().trim())));
+}
+
+/// `${task|truncate:80}`: at most that many characters, the last one being `…` when cut.
+fn truncate(argument: Option<&str>, value: Value) -> Result<Value, TemplateMappingError> {
+    let length = argument.map(|a| a.trim().parse::<usize>());
+
+    if let None | Some(Err(_)) | Some(Ok(0)) = length {
+        return Err(argument_error("truncate", "takes a length"));
+    }
+
+    let length = length.unwrap().unwrap();
+    let text = value.to_string();
+
+    if text.chars().count() <= length {
+        return Ok(Value::String(text));
+    }
+
+    let mut res: String = text.chars().take(length - 1).collect();
+    res.push('…');
+
+    return Ok(Value::String(res));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::spreadsheet_parsing::spreadsheet_data;
+
+    use super::*;
+
+    fn text(s: &str) -> Value {
+        return Value::String(String::from(s));
+    }
+
+    fn apply(name: &str, argument: Option<&str>, value: Value) -> String {
+        return Filters::default()
+            .apply(name, argument, value)
+            .unwrap()
+            .to_string();
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(apply("hours", None, text("1:30")), "1.5");
+        assert_eq!(apply("hours", None, text("1h15")), "1.25");
+        assert_eq!(apply("hours", None, text("PT45M")), "0.75");
+        assert_eq!(
+            apply("hours", None, Value::Duration(Duration::minutes(90))),
+            "1.5"
+        );
+        assert_eq!(apply("hours", None, Value::None), "0");
+
+        assert_eq!(apply("minutes", None, text("1h30")), "90");
+        assert_eq!(apply("minutes", None, text("90 min")), "90");
+        assert_eq!(apply("minutes", None, text("30s")), "0.5");
+        assert_eq!(apply("minutes", None, Value::Float(0.5)), "30");
+
+        let filters = Filters::default();
+        assert!(matches!(
+            filters.apply("hours", None, text("soon")),
+            Err(TemplateMappingError::NumberParsingError(_))
+        ));
+        assert!(filters.apply("hours", Some("2"), text("1:30")).is_err());
+        assert!(filters.apply("weeks", None, text("1:30")).is_err());
+    }
+
+    #[test]
+    fn dates() {
+        let day = spreadsheet_data::parse_iso_date("2024-10-01").unwrap();
+
+        assert_eq!(
+            apply("date", Some("%d/%m/%Y"), Value::Date(day)),
+            "01/10/2024"
+        );
+        assert_eq!(
+            apply("date", Some("%d/%m/%Y"), text("2024-10-01")),
+            "01/10/2024"
+        );
+        assert_eq!(apply("date", None, Value::Integer(45566)), "2024-10-01");
+        assert_eq!(apply("date", Some("%H:%M"), Value::Float(45566.5)), "12:00");
+        assert_eq!(apply("date", None, Value::None), "");
+
+        let filters = Filters::default();
+        assert!(filters.apply("date", None, text("tomorrow")).is_err());
+        assert!(filters.apply("date", Some("%Q"), Value::Date(day)).is_err());
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(apply("number", Some("2"), Value::Float(9.2)), "9.20");
+        assert_eq!(apply("number", Some("0"), Value::Float(2.5)), "3");
+        assert_eq!(apply("number", Some("2"), text("1.005")), "1.01");
+        assert_eq!(apply("number", None, Value::Integer(1234)), "1234");
+        assert_eq!(
+            apply("number", Some("1,fr"), Value::Float(1234.56)),
+            "1\u{202f}234,6"
+        );
+        assert_eq!(
+            apply("number", Some("2,en"), Value::Float(-1234567.5)),
+            "-1,234,567.50"
+        );
+        assert_eq!(apply("number", Some(",fr"), Value::Float(0.25)), "0,25");
+        assert_eq!(apply("number", Some("2"), Value::None), "");
+
+        let filters = Filters::default();
+        assert!(filters
+            .apply("number", Some("two"), Value::Float(1.0))
+            .is_err());
+        assert!(filters
+            .apply("number", Some("2,de"), Value::Float(1.0))
+            .is_err());
+        assert!(filters.apply("number", None, text("many")).is_err());
+    }
+
+    #[test]
+    fn text_filters() {
+        assert_eq!(apply("upper", None, text("Écrire")), "ÉCRIRE");
+        assert_eq!(apply("lower", None, text("Écrire")), "écrire");
+        assert_eq!(apply("trim", None, text("  task ")), "task");
+        assert_eq!(apply("truncate", Some("5"), text("Meeting")), "Meet…");
+        assert_eq!(apply("truncate", Some("7"), text("Meeting")), "Meeting");
+
+        assert!(Filters::default()
+            .apply("truncate", None, text("Meeting"))
+            .is_err());
+    }
+
+    #[test]
+    fn custom_filter() {
+        let mut filters = Filters::default();
+        filters.register("initials", |_, v| {
+            return Ok(Value::String(
+                v.to_string()
+                    .split_whitespace()
+                    .filter_map(|w| w.chars().next())
+                    .collect(),
+            ));
+        });
+
+        assert_eq!(
+            filters
+                .apply("initials", None, text("Jane Doe"))
+                .unwrap()
+                .to_string(),
+            "JD"
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::spreadsheet_parsing::spreadsheet_data::{TaskList, TaskRow, Value};
+
+use super::{TemplateMapping, TemplateMappingError};
+
+/// Rows merged into a single item, set under `[group]` in the template.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Grouping {
+    /// Key of each row, such as `${project}`. Rows sharing a key make one item.
+    pub by: String,
+    /// Values computed over the rows of a group, usable as `${name}` in outputs,
+    /// such as `hours = "sum(${time|hours})"`.
+    #[serde(default)]
+    pub aggregates: HashMap<String, String>,
+}
+
+/// Function computing an aggregate, with the field it reads on every row.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Aggregate {
+    Sum(String),
+    Count,
+    Min(String),
+    Max(String),
+    First(String),
+    /// Field and separator, `", "` by default.
+    Join(String, String),
+}
+
+impl Aggregate {
+    /// Reads `sum(...)`, `count()`, `min(...)`, `max(...)`, `first(...)` or
+    /// `join(..., 'separator')`.
+    pub fn parse(text: &str) -> Result<Aggregate, TemplateMappingError> {
+        let call = Regex::new(r"(?s)^\s*(\w+)\s*\((.*)\)\s*$").unwrap();
+        let captures = call.captures(text);
+
+        if let None = captures {
+            return Err(TemplateMappingError::TemplateMappingError(
+                String::from("Expected an aggregate such as sum(...), got ") + text,
+            ));
+        }
+
+        let captures = captures.unwrap();
+        let field = String::from(captures[2].trim());
+
+        match (&captures[1], field.is_empty()) {
+            ("count", true) => return Ok(Aggregate::Count),
+            ("count", false) => {
+                return Err(TemplateMappingError::TemplateMappingError(String::from(
+                    "count() takes no argument",
+                )))
+            }
+            (_, true) => {
+                return Err(TemplateMappingError::TemplateMappingError(format!(
+                    "{}() needs the field to aggregate",
+                    &captures[1]
+                )))
+            }
+            ("sum", _) => return Ok(Aggregate::Sum(field)),
+            ("min", _) => return Ok(Aggregate::Min(field)),
+            ("max", _) => return Ok(Aggregate::Max(field)),
+            ("first", _) => return Ok(Aggregate::First(field)),
+            ("join", _) => {
+                let separator = Regex::new(r"(?s)^(.*?)\s*,\s*'([^']*)'$").unwrap();
+
+                if let Some(c) = separator.captures(&field) {
+                    return Ok(Aggregate::Join(String::from(&c[1]), String::from(&c[2])));
+                }
+
+                return Ok(Aggregate::Join(field, String::from(", ")));
+            }
+            (name, _) => {
+                return Err(TemplateMappingError::TemplateMappingError(
+                    String::from("Unknown aggregate ") + name,
+                ))
+            }
+        }
+    }
+
+    /// Computes the aggregate over the rows at `indices`.
+    pub fn compute(
+        &self,
+        name: &str,
+        mapping: &TemplateMapping,
+        list: &TaskList,
+        indices: &[usize],
+    ) -> Result<Value, TemplateMappingError> {
+        let (field, pick): (&str, fn(Decimal, Decimal) -> Decimal) = match self {
+            Aggregate::Count => return Ok(Value::Integer(indices.len() as i64)),
+            Aggregate::First(field) => {
+                return mapping
+                    .apply_line_str(field, list, indices[0])
+                    .map(Value::String)
+                    .map_err(|e| row_error(list, indices[0], e.in_field(name)))
+            }
+            Aggregate::Join(field, separator) => {
+                let mut parts = Vec::new();
+
+                for i in indices {
+                    let part = mapping.apply_line_str(field, list, *i);
+
+                    if let Err(e) = part {
+                        return Err(row_error(list, *i, e.in_field(name)));
+                    }
+
+                    let part = part.unwrap();
+
+                    if !part.is_empty() {
+                        parts.push(part);
+                    }
+                }
+
+                return Ok(Value::String(parts.join(separator)));
+            }
+            Aggregate::Sum(field) => (field, |a, b| a + b),
+            Aggregate::Min(field) => (field, Decimal::min),
+            Aggregate::Max(field) => (field, Decimal::max),
+        };
+
+        let mut result: Option<Decimal> = None;
+
+        for i in indices {
+            let number = mapping.apply_line_number(field, list, *i);
+
+            if let Err(e) = number {
+                return Err(row_error(list, *i, e.in_field(name)));
+            }
+
+            let number = number.unwrap();
+            result = Some(result.map(|r| pick(r, number)).unwrap_or(number));
+        }
+
+        // Kept as text so that no decimal is lost on the way to the outputs.
+        return Ok(Value::String(
+            result.unwrap_or_default().normalize().to_string(),
+        ));
+    }
+}
+
+fn row_error(list: &TaskList, index: usize, e: TemplateMappingError) -> TemplateMappingError {
+    return TemplateMappingError::RowError(list.row_number(index).unwrap_or(index), Box::new(e));
+}
+
+/// Column of the grouped list holding an aggregate.
+pub fn aggregate_column(name: &str) -> String {
+    return String::from("[group] ") + name;
+}
+
+impl Grouping {
+    /// Merges the rows at `indices` by key, giving a list with one row per group, in
+    /// order of first appearance. Each row holds the cells of the first row of its group,
+    /// and the aggregates.
+    pub fn group(
+        &self,
+        mapping: &TemplateMapping,
+        list: &TaskList,
+        indices: &[usize],
+    ) -> Result<TaskList, TemplateMappingError> {
+        let mut aggregates = Vec::new();
+
+        for (name, text) in &self.aggregates {
+            if mapping.inputs.contains_key(name) {
+                return Err(TemplateMappingError::TemplateMappingError(
+                    String::from("An aggregate and an input are both named ") + name,
+                ));
+            }
+
+            let aggregate = Aggregate::parse(text);
+
+            if let Err(e) = aggregate {
+                return Err(e.in_field(name));
+            }
+
+            aggregates.push((name, aggregate.unwrap()));
+        }
+
+        let mut keys = Vec::<String>::new();
+        let mut groups = HashMap::<String, Vec<usize>>::new();
+
+        for i in indices {
+            let key = mapping.apply_line_str(&self.by, list, *i);
+
+            if let Err(e) = key {
+                return Err(row_error(list, *i, e.in_field("by")));
+            }
+
+            let key = key.unwrap();
+
+            if !groups.contains_key(&key) {
+                keys.push(key.clone());
+            }
+
+            groups.entry(key).or_default().push(*i);
+        }
+
+        let headers: Vec<String> = list.headers().into_iter().cloned().collect();
+        let mut rows = Vec::new();
+
+        for key in keys {
+            let members = &groups[&key];
+            let first = members[0];
+            let mut values = Vec::new();
+
+            for h in &headers {
+                values.push(list.get(h, first).unwrap_or(Value::None));
+            }
+
+            for (name, aggregate) in &aggregates {
+                let value = aggregate.compute(name, mapping, list, members);
+
+                if let Err(e) = value {
+                    return Err(e);
+                }
+
+                values.push(value.unwrap());
+            }
+
+            rows.push(TaskRow::new(
+                list.row_number(first).unwrap_or(first),
+                values,
+            ));
+        }
+
+        let headers = headers
+            .into_iter()
+            .chain(aggregates.iter().map(|(name, _)| aggregate_column(name)))
+            .collect();
+
+        return Ok(TaskList::from(headers, rows));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_aggregates() {
+        assert_eq!(
+            Aggregate::parse("sum(${time|hours})").unwrap(),
+            Aggregate::Sum(String::from("${time|hours}"))
+        );
+        assert_eq!(Aggregate::parse(" count( ) ").unwrap(), Aggregate::Count);
+        assert_eq!(
+            Aggregate::parse("join(${day|date:%d/%m}, ' - ')").unwrap(),
+            Aggregate::Join(String::from("${day|date:%d/%m}"), String::from(" - "))
+        );
+        assert_eq!(
+            Aggregate::parse("join(${task})").unwrap(),
+            Aggregate::Join(String::from("${task}"), String::from(", "))
+        );
+        assert_eq!(
+            Aggregate::parse("max(${a} * 2)").unwrap(),
+            Aggregate::Max(String::from("${a} * 2"))
+        );
+
+        assert!(Aggregate::parse("${time}").is_err());
+        assert!(Aggregate::parse("sum()").is_err());
+        assert!(Aggregate::parse("count(${a})").is_err());
+        assert!(Aggregate::parse("avg(${a})").is_err());
+    }
+}
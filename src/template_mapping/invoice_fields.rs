@@ -0,0 +1,530 @@
+use std::collections::HashMap;
+
+use chrono::{Datelike, Duration, Months, NaiveDate};
+use regex::Regex;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::spreadsheet_parsing::spreadsheet_data::{TaskList, TaskRow, Value};
+
+use super::{
+    grouping::Aggregate, split_names, CustomerRef, InvoiceItems, TemplateMapping,
+    TemplateMappingError,
+};
+
+/// Fields of the invoice itself, set under `[invoice]` in the template. Text fields take
+/// placeholders: inputs of the first row, `[invoice.aggregates]`, `--var` variables,
+/// and `today`, `split_key`, `sub_total`, `total` and `item_count`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct InvoiceTemplate {
+    /// Customer id, or text giving a customer name or id.
+    pub customer: Option<CustomerRef>,
+    /// Invoice number pattern.
+    pub number: Option<String>,
+    /// Invoice date as `YYYY-MM-DD`, else the latest date of `latest_date`, else today.
+    pub date: Option<String>,
+    /// Input whose latest date over the rows of the invoice is the invoice date.
+    pub latest_date: Option<String>,
+    /// Payment terms giving the due date, such as `net 30` or `end of month + 15`.
+    pub terms: Option<String>,
+    /// Days between the invoice date and the due date, when there are no `terms`.
+    pub due_days: Option<i64>,
+    /// Terms given on the command line, over `terms` and `due_days`.
+    #[serde(skip)]
+    pub payment_terms: Option<PaymentTerms>,
+    /// Currency code, such as `EUR`.
+    pub currency: Option<String>,
+    pub notes: Option<String>,
+    /// Amount taken off the invoice, or a percentage of it when ending with `%`.
+    pub discount: Option<String>,
+    /// Tax types applied to the whole invoice, by name and separated by semicolons.
+    pub taxes: Option<String>,
+    /// PDF template, the top-level `template_name` by default.
+    pub template: Option<String>,
+    /// Values computed over the rows of the invoice, as in `[group.aggregates]`.
+    #[serde(default)]
+    pub aggregates: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Discount {
+    Fixed(Decimal),
+    Percentage(Decimal),
+}
+
+/// When an invoice is due.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaymentTerms {
+    /// Days after the invoice date.
+    Net(i64),
+    /// Days after the end of the month of the invoice date.
+    EndOfMonth(i64),
+    Date(NaiveDate),
+}
+
+impl PaymentTerms {
+    /// Days after the invoice date, without `terms` nor `due_days`.
+    pub const DEFAULT: PaymentTerms = PaymentTerms::Net(30);
+
+    /// Reads `net 30`, `30 days`, `end of month`, `end of month + 15`, `eom + 15`,
+    /// `due on receipt` or a `YYYY-MM-DD` date.
+    pub fn parse(text: &str) -> Result<PaymentTerms, TemplateMappingError> {
+        let text = text.trim().to_lowercase();
+
+        if let Ok(date) = NaiveDate::parse_from_str(&text, "%Y-%m-%d") {
+            return Ok(PaymentTerms::Date(date));
+        }
+
+        if text == "due on receipt" {
+            return Ok(PaymentTerms::Net(0));
+        }
+
+        let net = Regex::new(r"^(?:net\s*)?(\d+)(?:\s*days?)?$").unwrap();
+
+        if let Some(c) = net.captures(&text) {
+            return Self::days(&c[1]).map(PaymentTerms::Net);
+        }
+
+        let end_of_month =
+            Regex::new(r"^(?:end of month|eom)(?:\s*\+\s*(\d+)(?:\s*days?)?)?$").unwrap();
+
+        if let Some(c) = end_of_month.captures(&text) {
+            return match c.get(1) {
+                Some(d) => Self::days(d.as_str()).map(PaymentTerms::EndOfMonth),
+                None => Ok(PaymentTerms::EndOfMonth(0)),
+            };
+        }
+
+        return Err(TemplateMappingError::TemplateMappingError(
+            String::from("Unknown payment terms: ") + &text,
+        ));
+    }
+
+    fn days(digits: &str) -> Result<i64, TemplateMappingError> {
+        return digits.parse().map_err(|_| {
+            TemplateMappingError::TemplateMappingError(format!(
+                "{} days is too long for payment terms",
+                digits
+            ))
+        });
+    }
+
+    /// Due date of an invoice dated `date`, which must not be earlier.
+    pub fn due_date(&self, date: NaiveDate) -> Result<NaiveDate, TemplateMappingError> {
+        let due = match self {
+            PaymentTerms::Net(days) => {
+                Duration::try_days(*days).and_then(|d| date.checked_add_signed(d))
+            }
+            PaymentTerms::EndOfMonth(days) => date
+                .with_day(1)
+                .and_then(|d| d.checked_add_months(Months::new(1)))
+                .zip(Duration::try_days(days - 1))
+                .and_then(|(d, days)| d.checked_add_signed(days)),
+            PaymentTerms::Date(d) => Some(*d),
+        };
+
+        if let None = due {
+            return Err(TemplateMappingError::TemplateMappingError(format!(
+                "The due date of an invoice dated {} is out of range",
+                date
+            )));
+        }
+
+        let due = due.unwrap();
+
+        if due < date {
+            return Err(TemplateMappingError::TemplateMappingError(format!(
+                "The invoice is due on {}, before its date {}",
+                due, date
+            )));
+        }
+
+        return Ok(due);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComputedInvoiceFields {
+    pub customer: Option<CustomerRef>,
+    pub number: Option<String>,
+    pub date: NaiveDate,
+    pub due_date: NaiveDate,
+    pub currency: Option<String>,
+    pub notes: Option<String>,
+    pub discount: Option<Discount>,
+    /// Names of the tax types applied to the whole invoice.
+    pub taxes: Vec<String>,
+    pub template_name: String,
+}
+
+/// Column of the invoice row holding a value of the context, apart from the columns of
+/// `[group]` aggregates.
+pub fn context_column(name: &str) -> String {
+    return String::from("[invoice] ") + name;
+}
+
+impl InvoiceTemplate {
+    /// Values placeholders of the invoice fields can use, on top of the inputs. Variables
+    /// and aggregates may not share the name of an input, while inputs hide the built-in
+    /// values of the same name.
+    fn context(
+        &self,
+        mapping: &TemplateMapping,
+        list: &TaskList,
+        invoice: &InvoiceItems,
+        variables: &HashMap<String, String>,
+        today: NaiveDate,
+    ) -> Result<Vec<(String, Value)>, TemplateMappingError> {
+        let number = |d: Decimal| Value::String(d.normalize().to_string());
+
+        for (name, kind) in variables
+            .keys()
+            .map(|n| (n, "A variable"))
+            .chain(self.aggregates.keys().map(|n| (n, "An aggregate")))
+        {
+            if mapping.inputs.contains_key(name) {
+                return Err(TemplateMappingError::TemplateMappingError(format!(
+                    "{} and an input are both named {}",
+                    kind, name
+                )));
+            }
+        }
+
+        let mut context: Vec<(String, Value)> = variables
+            .iter()
+            .map(|(k, v)| (k.clone(), Value::String(v.clone())))
+            .collect();
+
+        context.push((
+            String::from("today"),
+            Value::Date(today.and_hms_opt(0, 0, 0).unwrap()),
+        ));
+        context.push((
+            String::from("split_key"),
+            Value::String(invoice.split_key.clone().unwrap_or_default()),
+        ));
+        context.push((
+            String::from("sub_total"),
+            number(invoice.items.iter().map(|i| i.sub_total).sum()),
+        ));
+        context.push((
+            String::from("total"),
+            number(invoice.items.iter().map(|i| i.total).sum()),
+        ));
+        context.push((
+            String::from("item_count"),
+            Value::Integer(invoice.items.len() as i64),
+        ));
+
+        for (name, text) in &self.aggregates {
+            let aggregate = Aggregate::parse(text);
+
+            if let Err(e) = aggregate {
+                return Err(e.in_field(name));
+            }
+
+            let value = if invoice.rows.is_empty() {
+                Ok(Value::None)
+            } else {
+                aggregate
+                    .unwrap()
+                    .compute(name, mapping, list, &invoice.rows)
+            };
+
+            if let Err(e) = value {
+                return Err(e);
+            }
+
+            context.push((name.clone(), value.unwrap()));
+        }
+
+        return Ok(context);
+    }
+
+    /// A single row holding the first row of the invoice, then the values of
+    /// [`InvoiceTemplate::context`], against which fields are computed.
+    pub(super) fn data(
+        &self,
+        mapping: &TemplateMapping,
+        list: &TaskList,
+        invoice: &InvoiceItems,
+        variables: &HashMap<String, String>,
+        today: NaiveDate,
+    ) -> Result<TaskList, TemplateMappingError> {
+        let context = self.context(mapping, list, invoice, variables, today);
+
+        if let Err(e) = context {
+            return Err(e);
+        }
+
+        let headers: Vec<String> = list.headers().into_iter().cloned().collect();
+        let first = invoice.rows.first();
+        let mut values: Vec<Value> = headers
+            .iter()
+            .map(|h| match first {
+                Some(i) => list.get(h, *i).unwrap_or(Value::None),
+                None => Value::None,
+            })
+            .collect();
+        let mut names = headers;
+
+        for (name, value) in context.unwrap() {
+            if !mapping.inputs.contains_key(&name) {
+                names.push(context_column(&name));
+                values.push(value);
+            }
+        }
+
+        let row_number = first.and_then(|i| list.row_number(*i)).unwrap_or(0);
+
+        return Ok(TaskList::from(
+            names,
+            vec![TaskRow::new(row_number, values)],
+        ));
+    }
+
+    /// Latest date read from `input` on the rows of the invoice, ignoring unreadable ones.
+    fn latest_date(
+        mapping: &TemplateMapping,
+        list: &TaskList,
+        invoice: &InvoiceItems,
+        input: &str,
+    ) -> Result<NaiveDate, TemplateMappingError> {
+        let mut latest: Option<NaiveDate> = None;
+
+        for i in &invoice.rows {
+            let value = mapping.resolve_placeholder(input, list, *i);
+
+            if let Err(e) = value {
+                return Err(e);
+            }
+
+            if let Some(d) = value.unwrap().as_date() {
+                latest = latest.max(Some(d.date()));
+            }
+        }
+
+        if let None = latest {
+            return Err(TemplateMappingError::TemplateMappingError(
+                String::from("No date found in input ") + input,
+            ));
+        }
+
+        return Ok(latest.unwrap());
+    }
+
+    /// Computes the fields of one invoice.
+    pub fn compute(
+        &self,
+        mapping: &TemplateMapping,
+        list: &TaskList,
+        invoice: &InvoiceItems,
+        variables: &HashMap<String, String>,
+        today: NaiveDate,
+    ) -> Result<ComputedInvoiceFields, TemplateMappingError> {
+        let data = self.data(mapping, list, invoice, variables, today);
+
+        if let Err(e) = data {
+            return Err(e);
+        }
+
+        let data = data.unwrap();
+
+        let text = |field: &str, line: &Option<String>| match line {
+            Some(l) => mapping
+                .apply_line_str(l, &data, 0)
+                .map(|t| Some(String::from(t.trim())))
+                .map_err(|e| e.in_field(field)),
+            None => Ok(None),
+        };
+
+        let (customer, number, date, terms, currency, notes, taxes, template) = (
+            match &self.customer {
+                Some(CustomerRef::Name(n)) => text("customer", &Some(n.clone())),
+                _ => Ok(None),
+            },
+            text("number", &self.number),
+            text("date", &self.date),
+            text("terms", &self.terms),
+            text("currency", &self.currency),
+            text("notes", &self.notes),
+            text("taxes", &self.taxes),
+            text("template", &self.template),
+        );
+
+        if customer.is_err() {
+            return Err(customer.unwrap_err());
+        }
+
+        if number.is_err() {
+            return Err(number.unwrap_err());
+        }
+
+        if date.is_err() {
+            return Err(date.unwrap_err());
+        }
+
+        if terms.is_err() {
+            return Err(terms.unwrap_err());
+        }
+
+        if currency.is_err() {
+            return Err(currency.unwrap_err());
+        }
+
+        if notes.is_err() {
+            return Err(notes.unwrap_err());
+        }
+
+        if taxes.is_err() {
+            return Err(taxes.unwrap_err());
+        }
+
+        if template.is_err() {
+            return Err(template.unwrap_err());
+        }
+
+        let customer = match (&self.customer, customer.unwrap()) {
+            (Some(CustomerRef::Id(id)), _) => Some(CustomerRef::Id(*id)),
+            (_, Some(c)) if !c.is_empty() => Some(CustomerRef::parse(&c)),
+            _ => None,
+        };
+
+        let date = match date.unwrap() {
+            Some(d) => {
+                let parsed = Value::String(d.clone()).as_date();
+
+                if let None = parsed {
+                    return Err(TemplateMappingError::TemplateMappingError(
+                        String::from("Invalid invoice date: ") + &d,
+                    )
+                    .in_field("date"));
+                }
+
+                parsed.unwrap().date()
+            }
+            None => match &self.latest_date {
+                Some(input) => {
+                    let latest = Self::latest_date(mapping, list, invoice, input);
+
+                    if let Err(e) = latest {
+                        return Err(e.in_field("latest_date"));
+                    }
+
+                    latest.unwrap()
+                }
+                None => today,
+            },
+        };
+
+        let terms = match (&self.payment_terms, terms.unwrap(), self.due_days) {
+            (Some(t), _, _) => Ok(t.clone()),
+            (None, Some(t), _) => PaymentTerms::parse(&t).map_err(|e| e.in_field("terms")),
+            (None, None, Some(days)) => Ok(PaymentTerms::Net(days)),
+            (None, None, None) => Ok(PaymentTerms::DEFAULT),
+        };
+
+        if let Err(e) = terms {
+            return Err(e);
+        }
+
+        let due_date = terms.unwrap().due_date(date);
+
+        if let Err(e) = due_date {
+            return Err(e.in_field("terms"));
+        }
+
+        let discount = match &self.discount {
+            Some(d) => {
+                let (line, percentage) = match d.trim().strip_suffix('%') {
+                    Some(l) => (l, true),
+                    None => (d.as_str(), false),
+                };
+
+                let amount = mapping
+                    .apply_line_number(line, &data, 0)
+                    .map_err(|e| e.in_field("discount"));
+
+                if let Err(e) = amount {
+                    return Err(e);
+                }
+
+                let amount = amount.unwrap();
+
+                if percentage {
+                    Some(Discount::Percentage(amount))
+                } else {
+                    Some(Discount::Fixed(amount))
+                }
+            }
+            None => None,
+        };
+
+        let empty_to_none = |s: Option<String>| s.filter(|s| !s.is_empty());
+
+        return Ok(ComputedInvoiceFields {
+            customer,
+            number: empty_to_none(number.unwrap()),
+            date,
+            due_date: due_date.unwrap(),
+            currency: empty_to_none(currency.unwrap()),
+            notes: empty_to_none(notes.unwrap()),
+            discount,
+            taxes: split_names(&taxes.unwrap().unwrap_or_default()),
+            template_name: empty_to_none(template.unwrap())
+                .unwrap_or(String::from(mapping.template_name())),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payment_terms() {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+        let due = |text: &str| PaymentTerms::parse(text).unwrap().due_date(date).unwrap();
+
+        assert_eq!(due("net 30"), NaiveDate::from_ymd_opt(2024, 3, 1).unwrap());
+        assert_eq!(due("Net30"), due("30 days"));
+        assert_eq!(due("due on receipt"), date);
+        assert_eq!(
+            due("end of month"),
+            NaiveDate::from_ymd_opt(2024, 1, 31).unwrap()
+        );
+        assert_eq!(
+            due("End of month + 15"),
+            NaiveDate::from_ymd_opt(2024, 2, 15).unwrap()
+        );
+        assert_eq!(
+            PaymentTerms::EndOfMonth(0)
+                .due_date(NaiveDate::from_ymd_opt(2024, 2, 3).unwrap())
+                .unwrap(),
+            NaiveDate::from_ymd_opt(2024, 2, 29).unwrap()
+        );
+        assert_eq!(
+            due("eom+10 days"),
+            NaiveDate::from_ymd_opt(2024, 2, 10).unwrap()
+        );
+        assert_eq!(
+            due("2024-03-15"),
+            NaiveDate::from_ymd_opt(2024, 3, 15).unwrap()
+        );
+
+        assert!(PaymentTerms::parse("net").is_err());
+        assert!(PaymentTerms::parse("end of year").is_err());
+        assert!(PaymentTerms::parse("net 99999999999999999999").is_err());
+        assert!(PaymentTerms::parse("eom + 99999999999999999999").is_err());
+
+        assert!(PaymentTerms::Net(999999999).due_date(date).is_err());
+        assert!(PaymentTerms::Net(i64::MAX).due_date(date).is_err());
+        assert!(PaymentTerms::EndOfMonth(i64::MAX).due_date(date).is_err());
+        assert!(PaymentTerms::Net(-1).due_date(date).is_err());
+        assert!(
+            PaymentTerms::Date(NaiveDate::from_ymd_opt(2024, 1, 30).unwrap())
+                .due_date(date)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use chrono::NaiveDate;
+use regex::{Captures, Regex};
+
+/// Invoice number pattern, such as `INV-{YYYY}-{MM}-{seq:4}`. `{YYYY}`, `{YY}`, `{MM}` and
+/// `{DD}` come from the invoice date, and `{seq}` or `{seq:width}` is the number following
+/// the highest one among existing invoice numbers matching the pattern.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NumberPattern {
+    text: String,
+}
+
+impl NumberPattern {
+    pub fn new(text: &str) -> Self {
+        return Self {
+            text: String::from(text),
+        };
+    }
+
+    fn token_regex() -> Regex {
+        Regex::new(r"\{(YYYY|YY|MM|DD|seq(?::(\d+))?)\}").unwrap()
+    }
+
+    pub fn has_sequence(&self) -> bool {
+        return Self::token_regex()
+            .captures_iter(&self.text)
+            .any(|c| c[1].starts_with("seq"));
+    }
+
+    fn date_token(token: &str, date: NaiveDate) -> String {
+        match token {
+            "YYYY" => return date.format("%Y").to_string(),
+            "YY" => return date.format("%y").to_string(),
+            "MM" => return date.format("%m").to_string(),
+            _ => return date.format("%d").to_string(),
+        }
+    }
+
+    /// Number of an invoice dated `date`, with `seq` in place of the sequence.
+    pub fn format(&self, date: NaiveDate, seq: u64) -> String {
+        return Self::token_regex()
+            .replace_all(&self.text, |c: &Captures| {
+                if !c[1].starts_with("seq") {
+                    return Self::date_token(&c[1], date);
+                }
+
+                let width = c.get(2).map_or(0, |w| w.as_str().parse().unwrap_or(0));
+                return format!("{:0width$}", seq, width = width);
+            })
+            .into_owned();
+    }
+
+    /// Sequence of an existing number, when it matches the pattern for an invoice dated
+    /// `date`.
+    pub fn sequence_of(&self, number: &str, date: NaiveDate) -> Option<u64> {
+        let mut pattern = String::from("^");
+        let mut last = 0;
+
+        for c in Self::token_regex().captures_iter(&self.text) {
+            let token = c.get(0).unwrap();
+            pattern += &regex::escape(&self.text[last..token.start()]);
+
+            if c[1].starts_with("seq") {
+                pattern += r"(\d+)";
+            } else {
+                pattern += &regex::escape(&Self::date_token(&c[1], date));
+            }
+
+            last = token.end();
+        }
+
+        pattern += &regex::escape(&self.text[last..]);
+        pattern += "$";
+
+        return Regex::new(&pattern)
+            .unwrap()
+            .captures(number)
+            .and_then(|c| c.get(1))
+            .and_then(|s| s.as_str().parse().ok());
+    }
+
+    /// Number following the highest of `existing` matching the pattern, starting at 1.
+    pub fn next(&self, date: NaiveDate, existing: &[String]) -> String {
+        let last = existing
+            .iter()
+            .filter_map(|n| self.sequence_of(n, date))
+            .max()
+            .unwrap_or(0);
+
+        return self.format(date, last + 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbering() {
+        let pattern = NumberPattern::new("INV-{YYYY}-{MM}-{seq:4}");
+        let date = NaiveDate::from_ymd_opt(2024, 10, 31).unwrap();
+
+        assert!(pattern.has_sequence());
+        assert_eq!(pattern.format(date, 7), "INV-2024-10-0007");
+        assert_eq!(pattern.sequence_of("INV-2024-10-0012", date), Some(12));
+        assert_eq!(pattern.sequence_of("INV-2024-09-0012", date), None);
+        assert_eq!(pattern.sequence_of("INV-2024-10-12a", date), None);
+
+        let existing = vec![
+            String::from("INV-2024-10-0009"),
+            String::from("INV-2024-10-0011"),
+            String::from("INV-2024-09-0042"),
+            String::from("INV-000003"),
+        ];
+        assert_eq!(pattern.next(date, &existing), "INV-2024-10-0012");
+        assert_eq!(
+            pattern.next(NaiveDate::from_ymd_opt(2024, 11, 5).unwrap(), &existing),
+            "INV-2024-11-0001"
+        );
+
+        let pattern = NumberPattern::new("{YY}{DD}.{seq}");
+        assert_eq!(pattern.next(date, &[String::from("2431.9")]), "2431.10");
+
+        let pattern = NumberPattern::new("INV-{YYYY}-{MM}");
+        assert!(!pattern.has_sequence());
+        assert_eq!(pattern.format(date, 1), "INV-2024-10");
+    }
+}